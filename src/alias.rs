@@ -0,0 +1,79 @@
+use crate::nav::NavFolder;
+use crate::path::Path;
+use anyhow::{bail, ensure, Result};
+use log::debug;
+use std::collections::{BTreeMap, HashSet};
+
+/// old page paths (from fm aliases) mapped to the page that replaced them
+///
+/// all paths are normalized and include the source dir, just like NavPage.path
+#[derive(Debug, Default)]
+pub struct Aliases {
+    map: BTreeMap<Path, Path>,
+}
+
+impl Aliases {
+    /// the canonical page for an alias path
+    pub fn get(&self, path: &Path) -> Option<&Path> {
+        self.map.get(path)
+    }
+
+    /// (alias, canonical page)
+    pub fn iter(&self) -> impl Iterator<Item = (&Path, &Path)> {
+        self.map.iter()
+    }
+}
+
+// aliases can't point to anything that currently exists,
+// otherwise it would be ambiguous which one a link/url is referring to
+pub fn collect_aliases(src: &Path, root: &NavFolder) -> Result<Aliases> {
+    let src = &src.normalize();
+    let mut nav_paths = HashSet::new();
+
+    root.for_each_page(&mut |p| {
+        nav_paths.insert(p.path.normalize());
+    });
+
+    let mut map = BTreeMap::new();
+
+    root.try_for_each_page(&mut |page| {
+        for alias in &page.fm.aliases {
+            let path = src.join(alias).normalize();
+
+            ensure!(
+                alias.ends_with(".md"),
+                "alias {alias:?} in {} must be an .md file",
+                page.rel_path
+            );
+            ensure!(
+                path.strip_prefix(src).is_some(),
+                "alias {alias:?} in {} is outside of the source dir",
+                page.rel_path
+            );
+            ensure!(
+                !nav_paths.contains(&path),
+                "alias {alias:?} in {} collides with an existing page",
+                page.rel_path
+            );
+            ensure!(
+                !path.exists(),
+                "alias {alias:?} in {} collides with an existing file",
+                page.rel_path
+            );
+
+            if let Some(other) = map.insert(path, page.path.normalize()) {
+                bail!(
+                    "alias {alias:?} is claimed by both {} and {}",
+                    other.strip_prefix(src).unwrap_or(other),
+                    page.rel_path
+                );
+            }
+        }
+
+        Ok(())
+    })?;
+
+    debug!(target: "collect_aliases", "{map:#?}");
+
+    Ok(Aliases { map })
+}
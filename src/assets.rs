@@ -0,0 +1,78 @@
+use crate::links::page_links;
+use crate::md;
+use crate::nav::NavFolder;
+use crate::path::Path;
+use crate::source::Source;
+use log::{error, trace};
+use std::collections::HashSet;
+
+// images and links to files that aren't pages (downloads, etc.) are assets,
+// which have to exist in the source dir (as an extra file from dir_check)
+//
+// like links, ones in included content are relative to the included file
+//
+// extra files that no page references are probably leftovers
+
+pub struct AssetCheck {
+    // normalized
+    pub referenced: HashSet<Path>,
+    pub unreferenced: Vec<Path>,
+    // number of references to missing assets
+    pub missing: usize,
+}
+
+/// checks every asset reference, and finds the extra files that aren't referenced
+pub fn check_assets(root: &NavFolder, source: &Source, extra: &[Path]) -> AssetCheck {
+    let normalized = extra.iter().map(Path::normalize).collect::<HashSet<_>>();
+
+    let mut referenced = HashSet::new();
+    let mut missing = 0;
+    let mut checked = HashSet::new();
+
+    root.for_each_page(&mut |page| {
+        for (file, dest) in page_links(page, &mut checked, true) {
+            let dir = file.parent().unwrap().normalize();
+            let file = source.show(&file);
+
+            // pages are checked by check_links
+            let asset = md::split_link(dest)
+                .map(|(path, _)| path)
+                .filter(|path| !path.is_empty() && !path.ends_with('/') && !path.ends_with(".md"));
+            let Some(path) = asset else {
+                trace!(target: "check_assets", "skipping {dest}");
+                continue;
+            };
+
+            let target = dir.join(&*md::decode_path(path)).normalize();
+
+            if normalized.contains(&target) {
+                referenced.insert(target);
+            } else if target.is_dir() {
+                // a directory url
+                trace!(target: "check_assets", "skipping dir {dest}");
+            } else if target.exists() {
+                // outside the source dir, or ignored
+                error!(
+                    target: "check_assets",
+                    "{file}: asset {dest:?} isn't part of the source dir"
+                );
+                missing += 1;
+            } else {
+                error!(target: "check_assets", "{file}: missing asset {dest:?}");
+                missing += 1;
+            }
+        }
+    });
+
+    let unreferenced = extra
+        .iter()
+        .filter(|p| !referenced.contains(&p.normalize()))
+        .cloned()
+        .collect();
+
+    AssetCheck {
+        referenced,
+        unreferenced,
+        missing,
+    }
+}
@@ -0,0 +1,144 @@
+use crate::front_matter::FrontMatter;
+use crate::md::Outline;
+use crate::path::Path;
+use anyhow::Result;
+use log::{debug, trace};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs;
+
+// processing a page only depends on its content (and where it is, since includes and links are relative to it),
+// so the expensive parts are cached by path, and only used if the content hash still matches
+// (building also depends on the included files, so that's only used if they haven't changed either)
+//
+// the cache is yaml since nav elements use yaml tags
+
+const CACHE_PATH: &str = "target/nr-spec-md/cache.yml";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CachedPage {
+    // of the page's content
+    pub hash: String,
+    pub fm: FrontMatter,
+    // where the content starts (after the fm)
+    pub content_start: usize,
+    // none until the page has been built (see process::build_pages)
+    pub build: Option<CachedBuild>,
+}
+
+/// everything about a built page that doesn't need the built content itself
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CachedBuild {
+    pub title_h1: String,
+    pub fixed_content: String,
+    pub links: Vec<String>,
+    pub images: Vec<String>,
+    // (included file, dest) of the links and images in included content
+    pub included_links: Vec<(String, String)>,
+    pub outline: Outline,
+    // (path, content hash) of every included file
+    pub includes: Vec<(String, String)>,
+}
+
+impl CachedBuild {
+    /// whether every included file still has the same content
+    pub fn is_fresh(&self) -> bool {
+        self.includes
+            .iter()
+            .all(|(path, hash)| fs::read_to_string(path).is_ok_and(|s| self::hash(&s) == *hash))
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct CacheFile {
+    // a different version might process pages differently
+    version: String,
+    // by page path
+    pages: HashMap<String, CachedPage>,
+}
+
+#[derive(Debug, Default)]
+pub struct Cache {
+    // from the last run
+    old: HashMap<String, CachedPage>,
+    // used in this run (anything else is dropped when saving)
+    new: HashMap<String, CachedPage>,
+}
+
+impl Cache {
+    /// an invalid or outdated cache is just ignored
+    pub fn load() -> Cache {
+        let file = fs::read_to_string(CACHE_PATH)
+            .ok()
+            .and_then(|s| serde_yaml::from_str::<CacheFile>(&s).ok());
+
+        match file {
+            Some(file) if file.version == env!("CARGO_PKG_VERSION") => {
+                debug!(target: "cache", "loaded {} pages", file.pages.len());
+                Cache {
+                    old: file.pages,
+                    new: HashMap::new(),
+                }
+            }
+            _ => {
+                debug!(target: "cache", "no usable cache at {CACHE_PATH}");
+                Cache::default()
+            }
+        }
+    }
+
+    /// the page at `path` if its content hasn't changed, and marks it as used in this run
+    pub fn get(&mut self, path: &Path, hash: &str) -> Option<&CachedPage> {
+        let key = path.to_string();
+
+        if let Some(page) = self.old.remove(&key).filter(|page| page.hash == hash) {
+            self.new.insert(key.clone(), page);
+        }
+        self.new.get(&key).filter(|page| page.hash == hash)
+    }
+
+    /// a page that was already marked as used
+    pub fn peek(&self, path: &Path) -> Option<&CachedPage> {
+        self.new.get(&path.to_string())
+    }
+
+    pub fn insert(&mut self, path: &Path, page: CachedPage) {
+        self.new.insert(path.to_string(), page);
+    }
+
+    pub fn set_processed(&mut self, path: &Path, build: CachedBuild) {
+        if let Some(page) = self.new.get_mut(&path.to_string()) {
+            page.build = Some(build);
+        }
+    }
+
+    pub fn save(self) -> Result<()> {
+        let path = Path::new(CACHE_PATH);
+
+        if let Some(dir) = path.parent() {
+            unwrap!(fs::create_dir_all(&dir), "couldn't create dir {dir}");
+        }
+
+        let count = self.new.len();
+        let file = CacheFile {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            pages: self.new,
+        };
+        unwrap!(
+            fs::write(&path, serde_yaml::to_string(&file)?),
+            "couldn't write {path}"
+        );
+
+        trace!(target: "cache", "saved {count} pages");
+
+        Ok(())
+    }
+}
+
+pub fn hash(content: &str) -> String {
+    Sha256::digest(content.as_bytes())
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
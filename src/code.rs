@@ -0,0 +1,161 @@
+use crate::config::CodeBlocks;
+use crate::nav::NavFolder;
+use anyhow::{bail, Result};
+use log::error;
+use pulldown_cmark::{CodeBlockKind, Event, Tag};
+
+// fenced code block info strings look like `json {#example-id .invalid}`
+// the language comes first, then an optional attribute list
+//
+// `.invalid` opts a block out of syntax checking (for intentionally invalid examples)
+
+#[derive(Debug, Default)]
+pub struct Info<'a> {
+    pub lang: Option<&'a str>,
+    pub id: Option<&'a str>,
+    pub classes: Vec<&'a str>,
+}
+
+pub fn parse_info(info: &str) -> Result<Info<'_>> {
+    let info = info.trim();
+
+    let (lang, attrs) = match info.find('{') {
+        Some(i) => (info[..i].trim(), Some(info[i..].trim())),
+        None => (info, None),
+    };
+
+    if lang.contains(char::is_whitespace) {
+        bail!("info string {info:?} has more than one language");
+    }
+
+    let mut parsed = Info {
+        lang: (!lang.is_empty()).then_some(lang),
+        ..Info::default()
+    };
+
+    let Some(attrs) = attrs else {
+        return Ok(parsed);
+    };
+    let Some(attrs) = attrs.strip_prefix('{').and_then(|a| a.strip_suffix('}')) else {
+        bail!("unclosed attribute list in info string {info:?}");
+    };
+
+    for attr in attrs.split_whitespace() {
+        if let Some(id) = attr.strip_prefix('#') {
+            if parsed.id.replace(id).is_some() {
+                bail!("more than one id in info string {info:?}");
+            }
+        } else if let Some(class) = attr.strip_prefix('.') {
+            parsed.classes.push(class);
+        } else {
+            bail!("unknown attribute {attr:?} in info string {info:?}");
+        }
+    }
+
+    Ok(parsed)
+}
+
+// checks code block languages against the allowlist (if there is one),
+// and that json/yaml/toml blocks actually parse
+//
+// returns the number of problems
+pub fn check_code_blocks(root: &NavFolder, config: &CodeBlocks) -> usize {
+    let mut problems = 0;
+
+    root.for_each_page(&mut |page| {
+        let mut report = |line: usize, msg: String| {
+            error!(target: "check_code_blocks", "{}:{line}: {msg}", page.rel_path);
+            problems += 1;
+        };
+
+        let mut block = None;
+
+        for (e, loc) in page.doc.located_events() {
+            // only the page's own blocks are checked, not included ones
+            let Some(loc) = loc.filter(|loc| !loc.is_included()) else {
+                continue;
+            };
+
+            match e {
+                Event::Start(Tag::CodeBlock(kind)) => {
+                    let line = loc.line;
+
+                    let info = match &kind {
+                        CodeBlockKind::Fenced(info) => match parse_info(info) {
+                            Ok(info) => info,
+                            // the rest of the block can't be checked without knowing its language
+                            Err(e) => {
+                                report(line, e.to_string());
+                                continue;
+                            }
+                        },
+                        CodeBlockKind::Indented => Info::default(),
+                    };
+
+                    if let Some(allowed) = &config.languages {
+                        match info.lang {
+                            None => report(line, "code block is missing a language".into()),
+                            Some(lang) if !allowed.iter().any(|l| l == lang) => {
+                                report(line, format!("code block language {lang:?} isn't allowed"))
+                            }
+                            Some(_) => {}
+                        }
+                    }
+
+                    let check = !info.classes.contains(&"invalid");
+                    block = Some((line, info.lang.map(str::to_string), check, String::new()));
+                }
+                Event::Text(s) => {
+                    if let Some((_, _, _, text)) = &mut block {
+                        text.push_str(&s);
+                    }
+                }
+                Event::End(Tag::CodeBlock(_)) => {
+                    let Some((fence_line, lang, check, text)) = block.take() else {
+                        continue;
+                    };
+
+                    if !check {
+                        continue;
+                    }
+
+                    // the code starts on the line after the fence
+                    if let Some((line, msg)) = syntax_error(lang.as_deref(), &text) {
+                        report(fence_line + line, msg);
+                    }
+                }
+                _ => {}
+            }
+        }
+    });
+
+    problems
+}
+
+// (line in the code, message)
+fn syntax_error(lang: Option<&str>, code: &str) -> Option<(usize, String)> {
+    match lang? {
+        "json" => serde_json::from_str::<serde_json::Value>(code)
+            .err()
+            .map(|e| (e.line(), format!("invalid json: {e}"))),
+
+        "yaml" | "yml" => serde_yaml::Deserializer::from_str(code).find_map(|doc| {
+            serde::Deserialize::deserialize(doc)
+                .map(|_: serde_yaml::Value| ())
+                .err()
+                .map(|e| {
+                    let line = e.location().map_or(1, |l| l.line());
+                    (line, format!("invalid yaml: {e}"))
+                })
+        }),
+
+        "toml" => toml::from_str::<toml::Table>(code).err().map(|e| {
+            let line = e
+                .span()
+                .map_or(1, |s| code[..s.start].matches('\n').count() + 1);
+            (line, format!("invalid toml: {}", e.message()))
+        }),
+
+        _ => None,
+    }
+}
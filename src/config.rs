@@ -1,17 +1,51 @@
 use semver::VersionReq;
 use serde::Deserialize;
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Clone, Debug)]
 pub enum Mode {
     Build,
     Check,
     Fix,
+    Extract,
+    /// (old source dir, new source dir)
+    Diff(String, String),
+    Nav(NavFormat),
+    Init,
+    /// page path, relative to the source dir
+    New(String),
+    /// (old page, new page, add the old path as an alias)
+    Move(String, String, bool),
+}
+
+impl Mode {
+    /// whether the mode uses the built content of pages (otherwise cached pages aren't built at all)
+    pub fn needs_docs(&self) -> bool {
+        !matches!(
+            self,
+            Mode::Fix | Mode::Nav(_) | Mode::New(_) | Mode::Move(..)
+        )
+    }
+}
+
+impl Config {
+    /// whether warnings are errors (strict mode only applies to check)
+    pub fn strict_check(&self) -> bool {
+        self.strict && matches!(self.mode, Mode::Check)
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub enum NavFormat {
+    Tree,
+    Json,
 }
 
 #[derive(Debug)]
 pub struct Config {
     pub file: ConfigFile,
     pub mode: Mode,
+    /// `--strict` or `strict.enabled`
+    pub strict: bool,
 }
 
 #[derive(Debug, Deserialize)]
@@ -20,6 +54,16 @@ pub struct ConfigFile {
     pub version_req: VersionReq,
     pub metadata: Metadata,
     pub build: Build,
+    #[serde(default)]
+    pub requirements: Requirements,
+    #[serde(default)]
+    pub code_blocks: CodeBlocks,
+    #[serde(default)]
+    pub extract: Extract,
+    #[serde(default)]
+    pub grammar: Grammar,
+    #[serde(default)]
+    pub strict: Strict,
 }
 
 #[derive(Debug, Deserialize)]
@@ -27,10 +71,127 @@ pub struct Metadata {
     pub name: String,
     pub description: String,
     pub copyright: String,
+    /// the version of the specification itself (required for versioned builds)
+    #[serde(default)]
+    pub spec_version: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct Build {
     pub source: String,
     pub output: String,
+    #[serde(default)]
+    pub redirects: Redirects,
+    // prefix headings with their section number (eg. `## 4.2 Packets`)
+    #[serde(default)]
+    pub number_headings: bool,
+    // build into `<output>/<spec_version>/`, see versions.rs
+    #[serde(default)]
+    pub versioned: bool,
+    // .gitignore style patterns for files that aren't part of the spec, see nrignore.rs
+    #[serde(default)]
+    pub ignore: Vec<String>,
+    #[serde(default)]
+    pub copy_assets: CopyAssets,
+    #[serde(default)]
+    pub symlinks: Symlinks,
+    // other site generators to generate config for (mkdocs.yml is always generated)
+    #[serde(default)]
+    pub exports: Vec<Export>,
+}
+
+/// how fm aliases are turned into redirects
+#[derive(Copy, Clone, Debug, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Redirects {
+    /// a stub html page with a meta refresh for each alias
+    #[default]
+    Html,
+    /// `redirect_maps` for the mkdocs-redirects plugin
+    Mkdocs,
+}
+
+/// which extra (non .md) files build copies
+#[derive(Copy, Clone, Debug, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CopyAssets {
+    /// every file in the source dir
+    #[default]
+    All,
+    /// only files referenced by a page (see assets.rs)
+    Referenced,
+}
+
+/// a site generator (other than mkdocs) that can publish the docs dir
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Export {
+    /// book.toml, and SUMMARY.md in the docs dir
+    Mdbook,
+    /// sidebars.json
+    Docusaurus,
+}
+
+/// what to do with symlinks in the source dir
+#[derive(Copy, Clone, Debug, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Symlinks {
+    /// treat them like the file or dir they point to
+    #[default]
+    Follow,
+    /// act like they don't exist
+    Skip,
+    /// fail, since the spec shouldn't have any
+    Error,
+}
+
+/// strict mode makes check fail on every warning about the source dir (instead of just warning):
+/// unused pages, unreferenced assets, links to aliases, and dirs that are walked twice (through a symlink)
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Strict {
+    pub enabled: bool,
+    /// files (or dirs) that are intentionally unused, relative to the source dir
+    pub allow: Vec<String>,
+}
+
+/// rfc 2119 requirements (`MUST`, `SHOULD`, etc.)
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Requirements {
+    /// every requirement needs a `{#req-id}`
+    pub require_ids: bool,
+    /// build will generate a requirements register (requirements.md and requirements.json)
+    pub register: bool,
+}
+
+/// checks for fenced code blocks
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CodeBlocks {
+    /// if set, every code block needs a language from this list
+    pub languages: Option<Vec<String>>,
+}
+
+/// checks for abnf blocks
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Grammar {
+    /// top level rules, which don't need to be referenced by another rule
+    pub roots: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Extract {
+    /// where extract mode writes examples (and manifest.json)
+    pub output: String,
+}
+
+impl Default for Extract {
+    fn default() -> Self {
+        Extract {
+            output: "examples".to_string(),
+        }
+    }
 }
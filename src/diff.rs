@@ -0,0 +1,265 @@
+use crate::md::Document;
+use crate::nav::{NavFolder, NavPage};
+use crate::path::Path;
+use crate::requirements::{collect_requirements, Requirement};
+use pulldown_cmark::{Event, HeadingLevel};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+
+// compares two processed source trees, and describes the changes as markdown
+//
+// pages are matched by their path relative to the source dir,
+// or if a page moved, by its fm aliases (or failing that, a unique title)
+// sections are matched by heading id, and requirements by id (or their text if they don't have one)
+
+// a heading and its own content (up to the next heading of any level)
+struct DiffSection<'a> {
+    id: String,
+    title: String,
+    body: Vec<Event<'a>>,
+}
+
+// the h1 is the page title, so its section is the intro (with an empty id)
+fn sections(doc: &Document) -> Vec<DiffSection<'_>> {
+    let sections = doc.sections();
+    let events = doc.events().collect::<Vec<_>>();
+
+    sections
+        .iter()
+        .enumerate()
+        .map(|(i, s)| {
+            // only up to the next heading, subsections are compared on their own
+            let end = sections
+                .get(i + 1)
+                .map_or(events.len(), |next| next.title.start);
+            let h = &s.heading;
+
+            DiffSection {
+                id: if h.level == HeadingLevel::H1 {
+                    String::new()
+                } else {
+                    h.id.clone()
+                },
+                title: h.text.clone(),
+                body: events[s.title.end..end].to_vec(),
+            }
+        })
+        .collect()
+}
+
+// pages by their path relative to the source dir
+fn pages<'a>(root: &'a NavFolder, src: &Path) -> BTreeMap<Path, &'a NavPage> {
+    let src = src.normalize();
+    let mut pages = BTreeMap::new();
+
+    root.for_each_page(&mut |page| {
+        let path = page
+            .path
+            .normalize()
+            .strip_prefix(&src)
+            .expect("bug? path isn't in the source dir");
+        pages.insert(path, page);
+    });
+
+    pages
+}
+
+// requirements keyed by id, or their text if they don't have one
+fn requirements(root: &NavFolder) -> BTreeMap<String, Requirement> {
+    collect_requirements(root)
+        .into_iter()
+        .map(|r| (r.id.clone().unwrap_or_else(|| r.text.clone()), r))
+        .collect()
+}
+
+/// the changes from `old` to `new` as markdown (for release notes)
+pub fn changelog(old: &NavFolder, old_src: &Path, new: &NavFolder, new_src: &Path) -> String {
+    let old_pages = pages(old, old_src);
+    let new_pages = pages(new, new_src);
+
+    let mut added = new_pages
+        .keys()
+        .filter(|p| !old_pages.contains_key(*p))
+        .cloned()
+        .collect::<Vec<_>>();
+    let mut removed = Vec::new();
+    // (old path, new path)
+    let mut moved = Vec::new();
+
+    for (path, page) in &old_pages {
+        if new_pages.contains_key(path) {
+            continue;
+        }
+
+        let by_alias = added.iter().position(|p| {
+            new_pages[p]
+                .fm
+                .aliases
+                .iter()
+                .any(|a| Path::new(a).normalize() == *path)
+        });
+        let by_title = || {
+            let mut same = added
+                .iter()
+                .enumerate()
+                .filter(|(_, p)| new_pages[*p].name == page.name);
+            match (same.next(), same.next()) {
+                (Some((i, _)), None) => Some(i),
+                _ => None,
+            }
+        };
+
+        match by_alias.or_else(by_title) {
+            Some(i) => moved.push((path.clone(), added.remove(i))),
+            None => removed.push(path.clone()),
+        }
+    }
+
+    let mut out = String::new();
+
+    // pages
+
+    let mut lines = Vec::new();
+
+    for path in &added {
+        lines.push(format!("- Added `{path}` ({})", new_pages[path].name));
+    }
+    for path in &removed {
+        lines.push(format!("- Removed `{path}` ({})", old_pages[path].name));
+    }
+    for (from, to) in &moved {
+        lines.push(format!("- Moved `{from}` to `{to}`"));
+    }
+
+    let matched = old_pages
+        .keys()
+        .filter(|p| new_pages.contains_key(*p))
+        .map(|p| (p, p))
+        .chain(moved.iter().map(|(from, to)| (from, to)))
+        .collect::<Vec<_>>();
+
+    for (from, to) in &matched {
+        let (old_name, new_name) = (&old_pages[*from].name, &new_pages[*to].name);
+        if old_name != new_name {
+            lines.push(format!(
+                "- Renamed `{to}` from {old_name:?} to {new_name:?}"
+            ));
+        }
+    }
+
+    push_list(&mut out, "Pages", &lines);
+
+    // sections
+
+    let mut lines = Vec::new();
+
+    for (from, to) in &matched {
+        let changes = section_changes(old_pages[*from], new_pages[*to]);
+
+        if !changes.is_empty() {
+            lines.push(format!("- `{to}`"));
+            lines.extend(changes.iter().map(|c| format!("  - {c}")));
+        }
+    }
+
+    push_list(&mut out, "Sections", &lines);
+
+    // requirements
+
+    let old_reqs = requirements(old);
+    let new_reqs = requirements(new);
+    let mut lines = Vec::new();
+
+    for (key, req) in &new_reqs {
+        match old_reqs.get(key) {
+            None => lines.push(format!("- Added {}", describe(req, new_src))),
+            Some(old) if old.text != req.text => {
+                lines.push(format!("- Changed {}", describe(req, new_src)))
+            }
+            Some(_) => {}
+        }
+    }
+    for (key, req) in &old_reqs {
+        if !new_reqs.contains_key(key) {
+            lines.push(format!("- Removed {}", describe(req, old_src)));
+        }
+    }
+
+    push_list(&mut out, "Requirements", &lines);
+
+    if out.is_empty() {
+        out.push_str("No changes.\n");
+    }
+
+    out
+}
+
+fn push_list(out: &mut String, title: &str, lines: &[String]) {
+    if lines.is_empty() {
+        return;
+    }
+
+    if !out.is_empty() {
+        out.push('\n');
+    }
+
+    writeln!(out, "### {title}\n").unwrap();
+    lines.iter().for_each(|l| writeln!(out, "{l}").unwrap());
+}
+
+fn describe(req: &Requirement, src: &Path) -> String {
+    let page = req
+        .page
+        .strip_prefix(&src.normalize())
+        .expect("bug? path isn't in the source dir");
+
+    match &req.id {
+        Some(id) => format!("`{id}` in `{page}`: {}", req.text),
+        None => format!("requirement in `{page}`: {}", req.text),
+    }
+}
+
+fn section_changes(old: &NavPage, new: &NavPage) -> Vec<String> {
+    let old_sections = sections(&old.doc);
+    let new_sections = sections(&new.doc);
+
+    let old_by_id = old_sections
+        .iter()
+        .map(|s| (s.id.as_str(), s))
+        .collect::<HashMap<_, _>>();
+    let new_by_id = new_sections
+        .iter()
+        .map(|s| (s.id.as_str(), s))
+        .collect::<HashMap<_, _>>();
+
+    let mut changes = Vec::new();
+
+    for s in &new_sections {
+        let name = if s.id.is_empty() {
+            "the introduction".to_string()
+        } else {
+            format!("{:?} (`#{}`)", s.title, s.id)
+        };
+
+        match old_by_id.get(s.id.as_str()) {
+            None => changes.push(format!("Added {name}")),
+            Some(old) => {
+                // page renames are reported with the pages
+                if old.title != s.title && !s.id.is_empty() {
+                    changes.push(format!("Renamed {:?} to {name}", old.title));
+                }
+                if old.body != s.body {
+                    changes.push(format!("Changed {name}"));
+                }
+            }
+        }
+    }
+
+    for s in &old_sections {
+        if !new_by_id.contains_key(s.id.as_str()) {
+            changes.push(format!("Removed {:?} (`#{}`)", s.title, s.id));
+        }
+    }
+
+    changes
+}
@@ -1,7 +1,9 @@
+use crate::config::Symlinks;
 use crate::nav::NavFolder;
 use crate::path::Path;
-use anyhow::Result;
-use log::{debug, trace};
+use crate::source::Source;
+use anyhow::{bail, Result};
+use log::{debug, info, trace, warn};
 use std::collections::HashSet;
 use std::fs;
 use std::fs::Metadata;
@@ -9,53 +11,131 @@ use std::fs::Metadata;
 pub struct DirCheck {
     pub unused: Vec<Path>,
     pub extra: Vec<Path>,
+    // dirs that were skipped since they were already walked (through a symlink)
+    pub walked_twice: Vec<Path>,
 }
 
 // walks dir (recursively) and finds:
-// - .md files not in root (not in a nav)
+// - .md files not in root (not in a nav, and not included by a page)
 // - all !.md files
-pub fn dir_check(dir: &Path, root: &NavFolder) -> Result<DirCheck> {
-    trace!(target: "dir_check", "dir={dir:?}");
+//
+// ignored files (see nrignore.rs) are skipped
+pub fn dir_check(source: &Source, root: &NavFolder) -> Result<DirCheck> {
+    trace!(target: "dir_check", "dir={:?}", source.dir);
 
-    let mut nav_paths = HashSet::new();
+    let mut used_paths = HashSet::new();
 
     root.for_each_page(&mut |p| {
-        nav_paths.insert(&p.path);
+        used_paths.insert(p.path.normalize());
+        used_paths.extend(p.includes.iter().cloned());
     });
 
     let mut unused = Vec::new();
     let mut extra = Vec::new();
 
-    walk_dir_recursive(dir, &mut |path| {
+    let walked_twice = walk_source(source, &mut |path| {
         if path.extension() != Some("md".as_ref()) {
             // if it's not an .md file, it's extra
             extra.push(path);
-        } else if !nav_paths.contains(&path) {
-            // if it's an .md file, and it's not in the nav, it's unused
+        } else if !used_paths.contains(&path.normalize()) {
+            // if it's an .md file, and it's not in the nav (or included), it's unused
             unused.push(path);
         }
     })?;
 
     debug!(target: "dir_check", "extra: {extra:#?}");
 
-    Ok(DirCheck { unused, extra })
+    Ok(DirCheck {
+        unused,
+        extra,
+        walked_twice,
+    })
 }
 
-fn walk_dir_recursive(dir: &Path, cb: &mut impl FnMut(Path)) -> Result<()> {
-    let read_dir = unwrap!(fs::read_dir(dir), "couldn't read dir {dir}");
+pub fn walk_dir_recursive(dir: &Path, cb: &mut impl FnMut(Path)) -> Result<()> {
+    let mut skipped = Vec::new();
+
+    walk(
+        dir,
+        Symlinks::Follow,
+        &|_, _| true,
+        &Path::clone,
+        &mut HashSet::new(),
+        &mut skipped,
+        cb,
+    )?;
+
+    for dir in skipped {
+        warn!(target: "walk_dir_recursive", "skipping {dir}, it was already walked (symlink loop?)");
+    }
+
+    Ok(())
+}
+
+/// walks the source dir, skipping ignored files and handling symlinks as configured
+///
+/// returns the dirs that were skipped since they were already walked (through a symlink)
+pub fn walk_source(source: &Source, cb: &mut impl FnMut(Path)) -> Result<Vec<Path>> {
+    let filter = |path: &Path, is_dir| !source.ignore.is_ignored(path, is_dir);
+    let mut skipped = Vec::new();
+
+    walk(
+        &source.dir,
+        source.symlinks,
+        &filter,
+        &|path| source.show(path),
+        &mut HashSet::new(),
+        &mut skipped,
+        cb,
+    )?;
+
+    Ok(skipped)
+}
+
+// skips files and dirs where `filter(path, is_dir)` is false,
+// and shows paths in diagnostics as `show(path)`
+//
+// `visited` has the canonical path of every dir walked so far,
+// so a symlink loop (or a dir linked twice) is only walked once (the others go in `skipped`)
+fn walk(
+    dir: &Path,
+    symlinks: Symlinks,
+    filter: &impl Fn(&Path, bool) -> bool,
+    show: &impl Fn(&Path) -> Path,
+    visited: &mut HashSet<Path>,
+    skipped: &mut Vec<Path>,
+    cb: &mut impl FnMut(Path),
+) -> Result<()> {
+    let shown = show(dir);
+    let canonical = unwrap!(fs::canonicalize(dir), "couldn't resolve dir {shown}");
+
+    if !visited.insert(Path::new_owned(canonical)) {
+        skipped.push(dir.clone());
+        return Ok(());
+    }
+
+    let read_dir = unwrap!(fs::read_dir(dir), "couldn't read dir {shown}");
 
     for entry in read_dir {
-        let entry = entry.unwrap();
+        let entry = unwrap!(entry, "couldn't read dir {shown}");
         let path = Path::new_owned(entry.path());
-        let metadata = entry.metadata().unwrap();
+
+        let Some(metadata) = entry_metadata(&path, &show(&path), symlinks)? else {
+            continue;
+        };
         trace!(
             target: "walk_dir_recursive",
             "{} {path}",
             file_type_str(&metadata),
         );
 
+        if !filter(&path, metadata.is_dir()) {
+            trace!(target: "walk_dir_recursive", "skipping {path}");
+            continue;
+        }
+
         if metadata.is_dir() {
-            walk_dir_recursive(&path, cb)?;
+            walk(&path, symlinks, filter, show, visited, skipped, cb)?;
         } else {
             cb(path);
         }
@@ -64,6 +144,32 @@ fn walk_dir_recursive(dir: &Path, cb: &mut impl FnMut(Path)) -> Result<()> {
     Ok(())
 }
 
+/// the metadata of a file in a dir, following symlinks if the policy allows it
+///
+/// None if it should be skipped (`shown` is `path` as it's shown in diagnostics)
+pub fn entry_metadata(path: &Path, shown: &Path, symlinks: Symlinks) -> Result<Option<Metadata>> {
+    let metadata = unwrap!(
+        fs::symlink_metadata(path),
+        "couldn't read metadata of {shown}"
+    );
+
+    if !metadata.is_symlink() {
+        return Ok(Some(metadata));
+    }
+
+    match symlinks {
+        Symlinks::Follow => {
+            let target = unwrap!(fs::metadata(path), "broken symlink {shown}");
+            Ok(Some(target))
+        }
+        Symlinks::Skip => {
+            info!(target: "symlinks", "skipping symlink {shown}");
+            Ok(None)
+        }
+        Symlinks::Error => bail!("{shown} is a symlink, which isn't allowed by build.symlinks"),
+    }
+}
+
 fn file_type_str(meta: &Metadata) -> &'static str {
     if meta.is_file() {
         "file"
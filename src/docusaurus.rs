@@ -0,0 +1,87 @@
+use crate::nav::{NavFolder, NavItem};
+use crate::path::Path;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+// docusaurus uses the same docs dir as mkdocs, so doc ids are the doc path without `.md`
+//
+// folders become categories that link to their index, and categories don't link anywhere
+
+/// the sidebar in sidebars.json
+const SIDEBAR: &str = "spec";
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum SidebarItem {
+    Doc {
+        id: String,
+        label: String,
+    },
+    Category {
+        label: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        link: Option<CategoryLink>,
+        items: Vec<SidebarItem>,
+    },
+}
+
+#[derive(Serialize)]
+struct CategoryLink {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    id: String,
+}
+
+fn doc_id(doc: &Path) -> String {
+    let doc = doc.to_string();
+    doc.strip_suffix(".md").unwrap_or(&doc).to_string()
+}
+
+/// generates sidebars.json
+///
+/// `doc_path` maps a page's path to its path in the docs dir
+///
+/// `generated` are (name, doc path) of generated pages to add to the end of the sidebar
+pub fn sidebars_json(
+    root: &NavFolder,
+    doc_path: &impl Fn(&Path) -> Path,
+    generated: &[(&str, Path)],
+) -> String {
+    let mut items = vec![SidebarItem::Doc {
+        id: doc_id(&doc_path(&root.index.path)),
+        label: root.index.name.clone(),
+    }];
+    items.extend(root.children.iter().map(|i| sidebar_item(i, doc_path)));
+    items.extend(generated.iter().map(|(name, path)| SidebarItem::Doc {
+        id: doc_id(path),
+        label: name.to_string(),
+    }));
+
+    let sidebars = BTreeMap::from([(SIDEBAR, items)]);
+
+    serde_json::to_string_pretty(&sidebars).unwrap() + "\n"
+}
+
+fn sidebar_item(item: &NavItem, doc_path: &impl Fn(&Path) -> Path) -> SidebarItem {
+    let items = |children: &[NavItem]| children.iter().map(|i| sidebar_item(i, doc_path)).collect();
+
+    match item {
+        NavItem::Page(page) => SidebarItem::Doc {
+            id: doc_id(&doc_path(&page.path)),
+            label: page.name.clone(),
+        },
+        NavItem::Folder(folder) => SidebarItem::Category {
+            label: folder.index.name.clone(),
+            link: Some(CategoryLink {
+                kind: "doc",
+                id: doc_id(&doc_path(&folder.index.path)),
+            }),
+            items: items(&folder.children),
+        },
+        NavItem::Category(category) => SidebarItem::Category {
+            label: category.name.clone(),
+            link: None,
+            items: items(&category.children),
+        },
+    }
+}
@@ -0,0 +1,133 @@
+use crate::code::parse_info;
+use crate::md::Section;
+use crate::nav::NavFolder;
+use crate::path::Path;
+use anyhow::{bail, ensure, Result};
+use pulldown_cmark::{CodeBlockKind, Event, Tag};
+use serde::Serialize;
+use std::collections::HashMap;
+
+// code blocks with an id (```` ```json {#example-handshake} ````) are examples,
+// which can be extracted to use as test fixtures
+
+#[derive(Debug)]
+pub struct Example {
+    pub id: String,
+    pub lang: Option<String>,
+    // normalized
+    pub page: Path,
+    pub section: Section,
+    pub code: String,
+}
+
+impl Example {
+    /// file name in the output dir, eg. `example-handshake.json`
+    pub fn file_name(&self) -> String {
+        let ext = match self.lang.as_deref() {
+            None | Some("") => "txt",
+            Some("yml") => "yaml",
+            Some(lang) => lang,
+        };
+
+        format!("{}.{ext}", self.id)
+    }
+}
+
+pub fn collect_examples(root: &NavFolder) -> Result<Vec<Example>> {
+    let mut examples = Vec::new();
+    let mut seen = HashMap::<String, Path>::new();
+
+    root.try_for_each_page(&mut |page| {
+        let path = page.path.normalize();
+
+        let mut headings = page.outline.headings.iter();
+
+        let mut section = Section {
+            id: String::new(),
+            title: page.name.clone(),
+        };
+        let mut current: Option<Example> = None;
+
+        for e in page.doc.events() {
+            match e {
+                Event::Start(Tag::Heading(..)) => {
+                    let h = headings.next().expect("bug? heading count changed");
+                    section = Section {
+                        id: h.id.clone(),
+                        title: h.text.clone(),
+                    };
+                }
+                Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(info))) => {
+                    let info = unwrap!(parse_info(&info), "{}", page.rel_path);
+
+                    let Some(id) = info.id else {
+                        continue;
+                    };
+
+                    // ids are used as file names
+                    ensure!(
+                        id.chars()
+                            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.')),
+                        "{}: invalid example id {id:?}",
+                        page.rel_path
+                    );
+
+                    if let Some(other) = seen.insert(id.to_string(), page.rel_path.clone()) {
+                        bail!(
+                            "{}: duplicate example id {id:?} (already used in {other})",
+                            page.rel_path
+                        );
+                    }
+
+                    current = Some(Example {
+                        id: id.to_string(),
+                        lang: info.lang.map(str::to_string),
+                        page: path.clone(),
+                        section: section.clone(),
+                        code: String::new(),
+                    });
+                }
+                Event::Text(s) => {
+                    if let Some(example) = &mut current {
+                        example.code.push_str(&s);
+                    }
+                }
+                Event::End(Tag::CodeBlock(_)) => examples.extend(current.take()),
+                _ => {}
+            }
+        }
+
+        Ok(())
+    })?;
+
+    Ok(examples)
+}
+
+#[derive(Serialize)]
+struct ManifestEntry<'a> {
+    id: &'a str,
+    file: String,
+    language: Option<&'a str>,
+    page: String,
+    section: &'a str,
+    section_id: &'a str,
+}
+
+/// manifest.json, describing every extracted example
+///
+/// `doc_path` maps a page's path to its path relative to the source dir
+pub fn manifest_json(examples: &[Example], doc_path: &impl Fn(&Path) -> Path) -> String {
+    let entries = examples
+        .iter()
+        .map(|e| ManifestEntry {
+            id: &e.id,
+            file: e.file_name(),
+            language: e.lang.as_deref(),
+            page: doc_path(&e.page).to_string(),
+            section: &e.section.title,
+            section_id: &e.section.id,
+        })
+        .collect::<Vec<_>>();
+
+    serde_json::to_string_pretty(&entries).unwrap() + "\n"
+}
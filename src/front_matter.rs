@@ -4,15 +4,36 @@ use serde::{Deserialize, Serialize};
 use serde_yaml::value::{Tag, TaggedValue};
 use serde_yaml::{Mapping, Value};
 
-#[derive(Debug, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
 #[serde(deny_unknown_fields)]
 pub struct FrontMatter {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub name: Option<String>,
 
+    // shown next to the page in `<!-- nr-children -->` lists
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+
     // converts absent to/from an empty vec
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub nav: Vec<NavElem>,
+
+    // previous paths of this page (relative to the source root)
+    // build will generate redirects from each of these to the page
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub aliases: Vec<String>,
+
+    // glossary terms defined on this page
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub terms: Vec<String>,
+
+    // every h2 on this page is a glossary term
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub glossary: bool,
+}
+
+fn is_false(b: &bool) -> bool {
+    !b
 }
 
 // a folder is a nested structure with an index page and some child pages
@@ -185,7 +206,121 @@ impl From<NavElem> for Value {
     }
 }
 
-fn singleton_map(k: impl Into<Value>, v: impl Into<Value>) -> Value {
+// rewrites every single line nav entry in fm yaml into its canonical form
+// (eg. `- !index folder/index.md` -> `- folder/`), keeping comments and everything else as is
+//
+// multi line entries (categories) are left alone, but their children are rewritten
+pub fn canonicalize_nav_lines(yaml: &str) -> String {
+    map_nav_lines(yaml, |elem| Some(elem.clone()))
+}
+
+// rewrites the single line nav entries that `f` returns Some for (in the canonical form),
+// keeping comments and everything else as is
+pub fn map_nav_lines(yaml: &str, mut f: impl FnMut(&NavElem) -> Option<NavElem>) -> String {
+    let mut out = String::with_capacity(yaml.len());
+    let mut in_nav = false;
+
+    for line in yaml.split_inclusive('\n') {
+        let trimmed = line.trim_end();
+
+        // top level keys end the nav (list items and comments can be at column 0 too)
+        if !trimmed.is_empty() && !trimmed.starts_with([' ', '-', '#']) {
+            in_nav = trimmed.starts_with("nav:");
+        }
+
+        match in_nav.then(|| map_nav_line(trimmed, &mut f)).flatten() {
+            Some(canonical) => {
+                out.push_str(&canonical);
+                out.push_str(&line[trimmed.len()..]);
+            }
+            None => out.push_str(line),
+        }
+    }
+
+    out
+}
+
+fn map_nav_line(line: &str, f: impl FnOnce(&NavElem) -> Option<NavElem>) -> Option<String> {
+    let indent = line.len() - line.trim_start().len();
+    let item = line[indent..].strip_prefix("- ")?;
+
+    let elem = serde_yaml::from_str::<NavElem>(item).ok()?;
+    let new = f(&elem)?;
+
+    // the comment starts at the first ` #` that the entry doesn't need
+    let comment = item
+        .match_indices(" #")
+        .map(|(i, _)| i)
+        .find(|&i| {
+            serde_yaml::from_str::<NavElem>(&item[..i])
+                .is_ok_and(|e| Value::from(e) == Value::from(elem.clone()))
+        })
+        // (including the space before it)
+        .map_or("", |i| &item[item[..i].trim_end().len()..]);
+
+    let canonical = serde_yaml::to_string(&new).ok()?;
+    let canonical = canonical.trim_end();
+
+    // anything that doesn't fit on one line (categories) stays as it is
+    if canonical.contains('\n') {
+        return None;
+    }
+
+    Some(format!("{}- {canonical}{comment}", &line[..indent]))
+}
+
+// adds `- item` to the end of the top level list `key` in fm yaml (or adds the key if there isn't one),
+// keeping everything else as is
+pub fn append_list_line(yaml: &str, key: &str, item: &str) -> String {
+    let lines = yaml.split_inclusive('\n').collect::<Vec<_>>();
+
+    let is_key = |line: &str| {
+        line.strip_prefix(key)
+            .and_then(|rest| rest.strip_prefix(':'))
+            .is_some_and(|rest| rest.trim().is_empty() || rest.trim().starts_with('#'))
+    };
+
+    let Some(start) = lines.iter().position(|l| is_key(l)) else {
+        let sep = if yaml.is_empty() || yaml.ends_with('\n') {
+            ""
+        } else {
+            "\n"
+        };
+        return format!("{yaml}{sep}{key}:\n- {item}");
+    };
+
+    // the list ends at the next top level key
+    let end = lines[start + 1..]
+        .iter()
+        .position(|l| !l.trim_end().is_empty() && !l.starts_with([' ', '-', '#']))
+        .map_or(lines.len(), |i| start + 1 + i);
+
+    // after the last entry (trailing blank lines and comments usually belong to the next key)
+    let last = (start + 1..end)
+        .rev()
+        .find(|&i| !lines[i].trim().is_empty() && !lines[i].trim_start().starts_with('#'))
+        .unwrap_or(start);
+
+    // with the same indent as the other entries
+    let indent = lines[start + 1..end]
+        .iter()
+        .find(|l| l.trim_start().starts_with('-'))
+        .map_or("", |l| &l[..l.len() - l.trim_start().len()]);
+
+    let mut out = lines[..=last].concat();
+    if !out.ends_with('\n') {
+        out.push('\n');
+    }
+    out.push_str(&format!("{indent}- {item}"));
+    if last + 1 < lines.len() {
+        out.push('\n');
+        out.push_str(&lines[last + 1..].concat());
+    }
+
+    out
+}
+
+pub fn singleton_map(k: impl Into<Value>, v: impl Into<Value>) -> Value {
     let mut map = Mapping::with_capacity(1);
     map.insert(k.into(), v.into());
     Value::Mapping(map)
@@ -197,3 +332,66 @@ fn tagged_value(tag: impl Into<String>, value: impl Into<Value>) -> Value {
         value: value.into(),
     }))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn canonicalize_keeps_comments() {
+        let yaml = "# pages\nnav:\n- Intro: !index intro/index.md # first\n  # not an entry\n- guide/ # same\n";
+
+        assert_eq!(
+            canonicalize_nav_lines(yaml),
+            "# pages\nnav:\n- Intro: intro/ # first\n  # not an entry\n- guide/ # same\n",
+        );
+    }
+
+    #[test]
+    fn canonicalize_quoted_hash() {
+        let yaml = "nav:\n- \"Part #1\": part.md # comment\n";
+
+        assert_eq!(
+            canonicalize_nav_lines(yaml),
+            "nav:\n- 'Part #1': part.md # comment\n",
+        );
+    }
+
+    #[test]
+    fn canonicalize_categories() {
+        let yaml = "nav:\n- My Category:\n  - !index a/index.md\n  - b.md\n";
+
+        assert_eq!(
+            canonicalize_nav_lines(yaml),
+            "nav:\n- My Category:\n  - a/\n  - b.md\n",
+        );
+    }
+
+    #[test]
+    fn canonicalize_only_nav() {
+        let yaml = "aliases:\n- !index a/index.md\nnav:\n- !index b/index.md\nterms:\n- !index c/index.md\n";
+
+        assert_eq!(
+            canonicalize_nav_lines(yaml),
+            "aliases:\n- !index a/index.md\nnav:\n- b/\nterms:\n- !index c/index.md\n",
+        );
+    }
+
+    #[test]
+    fn append_after_last_entry() {
+        let yaml = "nav:\n  - a.md\n  - Cat:\n    - b.md\n  # trailing\n\ndescription: x";
+
+        assert_eq!(
+            append_list_line(yaml, "nav", "c.md"),
+            "nav:\n  - a.md\n  - Cat:\n    - b.md\n  - c.md\n  # trailing\n\ndescription: x",
+        );
+    }
+
+    #[test]
+    fn append_missing_key() {
+        assert_eq!(
+            append_list_line("nav:\n- a.md", "aliases", "old.md"),
+            "nav:\n- a.md\naliases:\n- old.md",
+        );
+    }
+}
@@ -0,0 +1,235 @@
+use crate::nav::{NavFolder, NavPage};
+use crate::path::Path;
+use anyhow::{bail, Result};
+use log::{debug, error};
+use pulldown_cmark::{CowStr, Event, HeadingLevel, LinkType, Tag};
+use std::collections::{HashMap, HashSet};
+
+// terms can be defined in 2 ways:
+// - a glossary page (fm `glossary: true`), where every h2 is a term
+// - fm `terms: [...]` on any page, linking to a heading with the same text if there is one
+//
+// terms are matched case insensitively on word boundaries
+
+#[derive(Debug)]
+pub struct Term {
+    // the capitalization used in the definition
+    pub name: String,
+    // normalized
+    pub page: Path,
+    pub anchor: Option<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct Glossary {
+    // longest first, so `Session Key` is matched before `Session`
+    terms: Vec<Term>,
+}
+
+impl Glossary {
+    // every term in the text that's on a word boundary: (byte range, term)
+    fn find<'s>(&'s self, text: &str) -> Vec<(usize, usize, &'s Term)> {
+        let mut found = Vec::new();
+        let mut i = 0;
+
+        while i < text.len() {
+            let at_boundary = !text[..i].ends_with(is_word_char);
+
+            let term = self.terms.iter().find(|t| {
+                let end = i + t.name.len();
+                at_boundary
+                    && text
+                        .get(i..end)
+                        .is_some_and(|s| s.eq_ignore_ascii_case(&t.name))
+                    && !text[end..].starts_with(is_word_char)
+            });
+
+            match term {
+                Some(t) => {
+                    found.push((i, i + t.name.len(), t));
+                    i += t.name.len();
+                }
+                None => i += text[i..].chars().next().unwrap().len_utf8(),
+            }
+        }
+
+        found
+    }
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+pub fn collect_glossary(root: &NavFolder) -> Result<Glossary> {
+    let mut terms = Vec::new();
+    let mut seen = HashMap::<String, Path>::new();
+
+    root.try_for_each_page(&mut |page| {
+        let path = page.path.normalize();
+        let headings = &page.outline.headings;
+
+        let mut add = |name: String, anchor: Option<String>| {
+            if let Some(other) = seen.insert(name.to_lowercase(), page.rel_path.clone()) {
+                bail!(
+                    "term {name:?} is defined in both {other} and {}",
+                    page.rel_path
+                );
+            }
+
+            terms.push(Term {
+                name,
+                page: path.clone(),
+                anchor,
+            });
+            Ok(())
+        };
+
+        if page.fm.glossary {
+            for h in headings.iter().filter(|h| h.level == HeadingLevel::H2) {
+                add(h.text.clone(), Some(h.id.clone()))?;
+            }
+        }
+
+        for term in &page.fm.terms {
+            let anchor = headings
+                .iter()
+                .find(|h| h.text.eq_ignore_ascii_case(term))
+                .map(|h| h.id.clone());
+
+            add(term.clone(), anchor)?;
+        }
+
+        Ok(())
+    })?;
+
+    terms.sort_by_key(|t| std::cmp::Reverse(t.name.len()));
+
+    debug!(target: "collect_glossary", "{terms:#?}");
+
+    Ok(Glossary { terms })
+}
+
+// replaces all the plain text in the events with whatever `f` returns
+// (not headings, links, or code, where terms shouldn't be linked)
+fn map_plain_text<'a>(
+    events: impl IntoIterator<Item = Event<'a>>,
+    mut f: impl FnMut(CowStr<'a>) -> Vec<Event<'a>>,
+) -> Vec<Event<'a>> {
+    let mut skip = 0;
+
+    events
+        .into_iter()
+        .flat_map(move |e| match e {
+            Event::Start(Tag::Heading(..) | Tag::Link(..) | Tag::Image(..) | Tag::CodeBlock(_)) => {
+                skip += 1;
+                vec![e]
+            }
+            Event::End(Tag::Heading(..) | Tag::Link(..) | Tag::Image(..) | Tag::CodeBlock(_)) => {
+                skip -= 1;
+                vec![e]
+            }
+            Event::Text(s) if skip == 0 => f(s),
+            e => vec![e],
+        })
+        .collect()
+}
+
+// reports terms that are never used outside of their own page,
+// and uses that aren't capitalized like the definition (except for capitalizing the first letter)
+//
+// returns the number of problems
+pub fn check_glossary(root: &NavFolder, glossary: &Glossary) -> usize {
+    let mut problems = 0;
+    let mut used = HashSet::new();
+
+    root.for_each_page(&mut |page| {
+        let path = page.path.normalize();
+
+        map_plain_text(page.doc.events(), |s| {
+            for (start, end, term) in glossary.find(&s) {
+                let text = &s[start..end];
+
+                if term.page != path {
+                    used.insert(&term.name);
+                }
+
+                if text != term.name && text != capitalize(&term.name) {
+                    error!(
+                        target: "check_glossary",
+                        "{}: term {:?} is capitalized inconsistently as {text:?}",
+                        page.rel_path,
+                        term.name,
+                    );
+                    problems += 1;
+                }
+            }
+
+            vec![Event::Text(s)]
+        });
+    });
+
+    for term in &glossary.terms {
+        if !used.contains(&term.name) {
+            error!(
+                target: "check_glossary",
+                "{}: term {:?} is never used",
+                term.page,
+                term.name,
+            );
+            problems += 1;
+        }
+    }
+
+    problems
+}
+
+fn capitalize(s: &str) -> String {
+    let mut chars = s.chars();
+    chars
+        .next()
+        .map(|c| c.to_uppercase().chain(chars).collect())
+        .unwrap_or_default()
+}
+
+// links the first use of each term on a page to its definition
+pub fn apply_glossary(page: &mut NavPage, glossary: &Glossary) {
+    let path = page.path.normalize();
+    let dir = path.parent().unwrap();
+
+    let mut linked = HashSet::new();
+
+    page.doc.transform(|events| {
+        map_plain_text(events, |s| {
+            let mut events = Vec::new();
+            let mut last = 0;
+
+            for (start, end, term) in glossary.find(&s) {
+                // don't link a term on the page that defines it
+                if term.page == path || !linked.insert(&term.name) {
+                    continue;
+                }
+
+                let mut dest = term.page.relative_from(&dir).to_string();
+                if let Some(anchor) = &term.anchor {
+                    dest = format!("{dest}#{anchor}");
+                }
+
+                let link = Tag::Link(LinkType::Inline, dest.into(), "".into());
+
+                events.push(Event::Text(s[last..start].to_string().into()));
+                events.push(Event::Start(link.clone()));
+                events.push(Event::Text(s[start..end].to_string().into()));
+                events.push(Event::End(link));
+                last = end;
+            }
+
+            if last == 0 {
+                return vec![Event::Text(s)];
+            }
+
+            events.push(Event::Text(s[last..].to_string().into()));
+            events
+        })
+    });
+}
@@ -0,0 +1,476 @@
+use crate::code::parse_info;
+use crate::config::Grammar as GrammarConfig;
+use crate::md::{Document, Loc};
+use crate::mkdocs::page_url;
+use crate::nav::{NavFolder, NavPage};
+use crate::path::Path;
+use log::error;
+use pulldown_cmark::{CodeBlockKind, Event, Tag};
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+
+// ```` ```abnf ```` blocks (rfc 5234) define the wire syntax, and together they form one grammar
+// every referenced rule has to be defined (once), and every rule has to be referenced
+// (except the configured roots)
+//
+// build links every rule reference to its definition
+
+// rfc 5234 appendix B
+const CORE_RULES: &[&str] = &[
+    "alpha", "bit", "char", "cr", "crlf", "ctl", "digit", "dquote", "hexdig", "htab", "lf", "lwsp",
+    "octet", "sp", "vchar", "wsp",
+];
+
+#[derive(Debug)]
+struct Rule<'a> {
+    // byte offset in the block
+    offset: usize,
+    name: &'a str,
+    // `=/`
+    incremental: bool,
+    // line in the block (0 indexed)
+    line: usize,
+    // (byte offset in the block, name)
+    refs: Vec<(usize, &'a str)>,
+}
+
+// (line in the block, message)
+type SyntaxError = (usize, String);
+
+fn is_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '-'
+}
+
+fn parse(code: &str) -> Result<Vec<Rule<'_>>, SyntaxError> {
+    let mut rules: Vec<Rule<'_>> = Vec::new();
+    let mut offset = 0;
+
+    for (i, line) in code.split_inclusive('\n').enumerate() {
+        let start = offset;
+        offset += line.len();
+
+        let content = line.split(';').next().unwrap();
+
+        if content.trim().is_empty() {
+            continue;
+        }
+
+        // indented lines continue the previous rule
+        if line.starts_with(char::is_whitespace) {
+            let Some(rule) = rules.last_mut() else {
+                return Err((i, "continuation line without a rule".into()));
+            };
+            elements(line, start, i, &mut rule.refs)?;
+            continue;
+        }
+
+        let name_len = line.find(|c| !is_name_char(c)).unwrap_or(line.len());
+        let name = &line[..name_len];
+
+        if !name.starts_with(|c: char| c.is_ascii_alphabetic()) {
+            return Err((i, format!("invalid rule name in {:?}", line.trim_end())));
+        }
+
+        let rest = line[name_len..].trim_start();
+        let (incremental, defined_as) = if rest.starts_with("=/") {
+            (true, 2)
+        } else if rest.starts_with('=') {
+            (false, 1)
+        } else {
+            return Err((i, format!("expected `=` or `=/` after rule name {name:?}")));
+        };
+
+        let elements_start = line.len() - rest.len() + defined_as;
+
+        let mut rule = Rule {
+            offset: start,
+            name,
+            incremental,
+            line: i,
+            refs: Vec::new(),
+        };
+        elements(
+            &line[elements_start..],
+            start + elements_start,
+            i,
+            &mut rule.refs,
+        )?;
+        rules.push(rule);
+    }
+
+    Ok(rules)
+}
+
+// finds the rule references in (part of) a line, starting at `offset` in the block
+fn elements<'a>(
+    text: &'a str,
+    offset: usize,
+    line: usize,
+    refs: &mut Vec<(usize, &'a str)>,
+) -> Result<(), SyntaxError> {
+    let mut chars = text.char_indices().peekable();
+
+    // skips everything up to (and including) `end`
+    let skip_to = |chars: &mut std::iter::Peekable<std::str::CharIndices>, end: char| {
+        chars
+            .by_ref()
+            .find(|(_, c)| *c == end)
+            .map(|_| ())
+            .ok_or_else(|| (line, format!("missing closing `{end}`")))
+    };
+
+    while let Some((i, c)) = chars.next() {
+        match c {
+            ';' => break,
+            '"' => skip_to(&mut chars, '"')?,
+            '<' => skip_to(&mut chars, '>')?,
+            // %x41-5A, %d13.10, %s"abc"
+            '%' => {
+                while chars
+                    .next_if(|(_, c)| is_name_char(*c) || *c == '.')
+                    .is_some()
+                {}
+            }
+            c if c.is_ascii_alphabetic() => {
+                let mut end = i + 1;
+                while let Some((j, c)) = chars.next_if(|(_, c)| is_name_char(*c)) {
+                    end = j + c.len_utf8();
+                }
+                refs.push((offset + i, &text[i..end]));
+            }
+            // repetition, alternatives, and grouping
+            c if c.is_ascii_digit() || c.is_whitespace() || "*/()[]".contains(c) => {}
+            c => return Err((line, format!("unexpected character {c:?}"))),
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(Debug)]
+struct Block {
+    // of the whole code block (including the fences), in the document's events
+    range: Range<usize>,
+    // of the fence
+    loc: Option<Loc>,
+    code: String,
+    // not `.invalid`
+    check: bool,
+}
+
+fn abnf_blocks(doc: &Document) -> Vec<Block> {
+    let mut blocks = Vec::new();
+    let mut current = None;
+
+    for (i, (e, loc)) in doc.located_events().enumerate() {
+        match e {
+            Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(info))) => {
+                // invalid info strings are reported by check_code_blocks
+                let Ok(info) = parse_info(&info) else {
+                    continue;
+                };
+
+                if info.lang == Some("abnf") {
+                    current = Some(Block {
+                        range: i..i,
+                        loc,
+                        code: String::new(),
+                        check: !info.classes.contains(&"invalid"),
+                    });
+                }
+            }
+            Event::Text(s) => {
+                if let Some(block) = &mut current {
+                    block.code.push_str(&s);
+                }
+            }
+            Event::End(Tag::CodeBlock(_)) => {
+                if let Some(mut block) = current.take() {
+                    block.range.end = i + 1;
+                    blocks.push(block);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    blocks
+}
+
+fn anchor(name: &str) -> String {
+    format!("abnf-{}", name.to_lowercase())
+}
+
+// reports syntax errors, undefined and duplicate rules, and rules that are never referenced
+//
+// returns the number of problems
+pub fn check_grammar(root: &NavFolder, config: &GrammarConfig) -> usize {
+    let mut problems = 0;
+
+    // lowercase name -> (name, location)
+    let mut defined = HashMap::<String, (String, String)>::new();
+    // (lowercase name, name, location)
+    let mut incremental = Vec::new();
+    // (lowercase name, name, location, the rule it's in)
+    let mut references = Vec::new();
+    // fences of the blocks checked so far, since a file can be included more than once
+    let mut checked = HashSet::new();
+
+    // the same (built) content as collect_grammar and apply_grammar, so included blocks are checked too
+    root.for_each_page(&mut |page| {
+        for block in abnf_blocks(&page.doc).into_iter().filter(|b| b.check) {
+            let Some(fence) = block.loc else {
+                continue;
+            };
+            if !checked.insert(page.doc.location(fence)) {
+                continue;
+            }
+
+            // the code starts on the line after the fence
+            let location = |line: usize| {
+                let mut loc = fence;
+                loc.line += line + 1;
+                page.doc.location(loc)
+            };
+
+            let rules = match parse(&block.code) {
+                Ok(rules) => rules,
+                Err((line, msg)) => {
+                    error!(target: "check_grammar", "{}: invalid abnf: {msg}", location(line));
+                    problems += 1;
+                    continue;
+                }
+            };
+
+            for rule in rules {
+                let key = rule.name.to_lowercase();
+
+                for (offset, name) in &rule.refs {
+                    // rules can span multiple lines
+                    let line = block.code[..*offset].matches('\n').count();
+                    references.push((
+                        name.to_lowercase(),
+                        name.to_string(),
+                        location(line),
+                        key.clone(),
+                    ));
+                }
+
+                if rule.incremental {
+                    incremental.push((key, rule.name.to_string(), location(rule.line)));
+                } else if let Some((_, other)) = defined.get(&key) {
+                    error!(
+                        target: "check_grammar",
+                        "{}: rule {:?} is already defined at {other}",
+                        location(rule.line),
+                        rule.name,
+                    );
+                    problems += 1;
+                } else {
+                    defined.insert(key, (rule.name.to_string(), location(rule.line)));
+                }
+            }
+        }
+    });
+
+    let is_defined = |key: &str| defined.contains_key(key) || CORE_RULES.contains(&key);
+
+    for (key, name, location) in &incremental {
+        if !is_defined(key) {
+            error!(
+                target: "check_grammar",
+                "{location}: `=/` adds to rule {name:?}, which isn't defined"
+            );
+            problems += 1;
+        }
+    }
+
+    let mut used = HashSet::new();
+
+    for (key, name, location, rule) in &references {
+        if !is_defined(key) {
+            error!(target: "check_grammar", "{location}: rule {name:?} isn't defined");
+            problems += 1;
+        }
+
+        // recursion doesn't count as a use
+        if key != rule {
+            used.insert(key.as_str());
+        }
+    }
+
+    for root in &config.roots {
+        if !defined.contains_key(&root.to_lowercase()) {
+            error!(target: "check_grammar", "grammar root {root:?} isn't defined");
+            problems += 1;
+        }
+    }
+
+    let mut unused = defined
+        .iter()
+        .filter(|(key, _)| !used.contains(key.as_str()))
+        .filter(|(key, _)| !config.roots.iter().any(|r| r.eq_ignore_ascii_case(key)))
+        .map(|(_, (name, location))| (location, name))
+        .collect::<Vec<_>>();
+    unused.sort();
+
+    for (location, name) in unused {
+        error!(target: "check_grammar", "{location}: rule {name:?} is never referenced");
+        problems += 1;
+    }
+
+    problems
+}
+
+/// where every rule is defined (lowercase name -> normalized page)
+#[derive(Debug, Default)]
+pub struct Grammar {
+    rules: HashMap<String, Path>,
+}
+
+pub fn collect_grammar(root: &NavFolder) -> Grammar {
+    let mut rules = HashMap::new();
+
+    root.for_each_page(&mut |page| {
+        let path = page.path.normalize();
+
+        for block in abnf_blocks(&page.doc) {
+            let Ok(parsed) = parse(&block.code) else {
+                continue;
+            };
+
+            // duplicates are reported by check_grammar, the first one wins
+            for rule in parsed.iter().filter(|r| !r.incremental) {
+                rules
+                    .entry(rule.name.to_lowercase())
+                    .or_insert_with(|| path.clone());
+            }
+        }
+    });
+
+    Grammar { rules }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+// replaces abnf blocks with html, where definitions have an id and references link to them
+// (links in a code block can't be written in markdown)
+pub fn apply_grammar(page: &mut NavPage, grammar: &Grammar, doc_path: &impl Fn(&Path) -> Path) {
+    let path = page.path.normalize();
+    let up = "../".repeat(page_url(&doc_path(&path)).matches('/').count());
+
+    let mut replacements = Vec::new();
+
+    for block in abnf_blocks(&page.doc) {
+        // broken blocks are left alone (and reported by check_grammar)
+        let Ok(rules) = parse(&block.code) else {
+            continue;
+        };
+
+        let mut html = String::from(r#"<pre><code class="language-abnf">"#);
+        let mut pos = 0;
+
+        for rule in &rules {
+            let names = std::iter::once((rule.offset, rule.name, true)).chain(
+                rule.refs
+                    .iter()
+                    .map(|(offset, name)| (*offset, *name, false)),
+            );
+
+            for (offset, name, is_definition) in names {
+                html.push_str(&escape(&block.code[pos..offset]));
+                pos = offset + name.len();
+
+                let key = name.to_lowercase();
+
+                if is_definition && !rule.incremental && grammar.rules.get(&key) == Some(&path) {
+                    html.push_str(&format!(r#"<span id="{}">{name}</span>"#, anchor(name)));
+                } else if let Some(page) = grammar.rules.get(&key) {
+                    let href = if *page == path {
+                        format!("#{}", anchor(name))
+                    } else {
+                        format!("{up}{}#{}", page_url(&doc_path(page)), anchor(name))
+                    };
+                    html.push_str(&format!(r#"<a href="{href}">{name}</a>"#));
+                } else {
+                    html.push_str(name);
+                }
+            }
+        }
+
+        html.push_str(&escape(&block.code[pos..]));
+        html.push_str("</code></pre>\n");
+
+        replacements.push((block.range, html));
+    }
+
+    if replacements.is_empty() {
+        return;
+    }
+
+    page.doc.transform(|events| {
+        let mut out = Vec::with_capacity(events.len());
+        let mut replacements = replacements.into_iter().peekable();
+
+        for (i, e) in events.into_iter().enumerate() {
+            match replacements.peek() {
+                Some((range, _)) if range.contains(&i) => {
+                    // the html stays inside the block's container (list item, block quote)
+                    if i + 1 == range.end {
+                        let (_, html) = replacements.next().unwrap();
+                        out.push(Event::Html(html.into()));
+                    }
+                }
+                _ => out.push(e),
+            }
+        }
+
+        out
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_rules_and_refs() {
+        let code = "msg = hdr 1*body ; comment ack\nhdr = %x41-5A \"lit body\" <prose ack>\n";
+        let rules = parse(code).unwrap();
+
+        assert_eq!(rules.len(), 2);
+        assert_eq!(rules[0].name, "msg");
+        assert_eq!(rules[0].line, 0);
+        assert_eq!(rules[0].refs, [(6, "hdr"), (12, "body")],);
+        assert_eq!(rules[1].name, "hdr");
+        assert_eq!(rules[1].offset, code.find("hdr =").unwrap());
+        assert!(rules[1].refs.is_empty());
+    }
+
+    #[test]
+    fn parse_continuations_and_incremental() {
+        let code = "msg = a\n      / b\n\nmsg =/ c\n";
+        let rules = parse(code).unwrap();
+
+        assert_eq!(rules.len(), 2);
+        assert_eq!(
+            rules[0].refs.iter().map(|(_, r)| *r).collect::<Vec<_>>(),
+            ["a", "b"],
+        );
+        assert!(!rules[0].incremental);
+        assert!(rules[1].incremental);
+        assert_eq!(rules[1].line, 3);
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(parse("  a\n").unwrap_err().0, 0);
+        assert_eq!(parse("a = b\n1x = c\n").unwrap_err().0, 1);
+        assert_eq!(parse("a = b\nb c\n").unwrap_err().0, 1);
+        assert_eq!(parse("a = \"open\n").unwrap_err().0, 0);
+    }
+}
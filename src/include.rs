@@ -0,0 +1,165 @@
+use crate::md;
+use crate::path::Path;
+use crate::source::Source;
+use anyhow::{bail, ensure, Context, Result};
+use log::trace;
+use std::fs;
+use std::ops::Range;
+
+// `<!-- nr-include: snippets/table.md -->` (on its own line) is replaced with that file's content
+// paths are relative to the file with the directive (and can't leave the source dir),
+// and included files can include other files
+//
+// `<!-- nr-include: snippets/table.md#heading -->` only includes that heading's section
+// `<!-- nr-include: snippets/table.md lines=3-10 -->` only includes those lines (1 indexed, inclusive)
+
+#[derive(Debug)]
+struct Directive<'a> {
+    path: &'a str,
+    section: Option<&'a str>,
+    lines: Option<(usize, usize)>,
+}
+
+fn parse_args(args: &str) -> Result<Directive<'_>> {
+    let mut args = args.split_whitespace();
+
+    let Some(target) = args.next() else {
+        bail!("missing include path");
+    };
+
+    let (path, section) = match target.split_once('#') {
+        Some((path, section)) => (path, Some(section)),
+        None => (target, None),
+    };
+
+    let mut lines = None;
+
+    for arg in args {
+        let Some(range) = arg.strip_prefix("lines=") else {
+            bail!("unknown include option {arg:?}");
+        };
+
+        let (start, end) = range.split_once('-').unwrap_or((range, range));
+        let start: usize = start.parse().context("invalid line range")?;
+        let end: usize = end.parse().context("invalid line range")?;
+
+        ensure!(
+            start >= 1 && start <= end,
+            "invalid line range {range:?} (lines start at 1)"
+        );
+
+        lines = Some((start, end));
+    }
+
+    ensure!(
+        section.is_none() || lines.is_none(),
+        "can't select both a section and lines"
+    );
+
+    Ok(Directive {
+        path,
+        section,
+        lines,
+    })
+}
+
+/// expands every include in `doc` (which was read from `file`)
+///
+/// `stack` is the chain of files currently being expanded (for cycle detection),
+/// and every included file is added to `included`
+pub fn expand(
+    mut doc: md::Document,
+    file: &Path,
+    source: &Source,
+    stack: &mut Vec<Path>,
+    included: &mut Vec<Path>,
+) -> Result<md::Document> {
+    let dir = file.parent().unwrap();
+
+    // every file here is in the source dir, so errors show them relative to it
+    let rel = |path: &Path| {
+        source
+            .relative(path)
+            .expect("bug? included file isn't in the source dir")
+    };
+    let file = rel(file);
+
+    doc.replace_directives(|name, args| {
+        if name != "include" {
+            return Ok(None);
+        }
+
+        let directive = unwrap!(parse_args(args), "invalid include {args:?} in {file}");
+
+        trace!(target: "include", "{file}: {directive:?}");
+
+        let path = unwrap!(
+            source.resolve(&dir, directive.path),
+            "bad include in {file}"
+        );
+
+        if stack.contains(&path) {
+            let chain = stack
+                .iter()
+                .map(|p| rel(p).to_string())
+                .collect::<Vec<_>>()
+                .join(" -> ");
+            bail!("include cycle: {chain} -> {}", rel(&path));
+        }
+
+        let raw = unwrap!(
+            fs::read_to_string(&path),
+            "couldn't read included file {}",
+            rel(&path)
+        );
+
+        let snippet = if let Some((start, end)) = directive.lines {
+            // lines are of the whole file (so they match what an editor shows)
+            let lines = raw.lines().count();
+            ensure!(
+                end <= lines,
+                "{file}: {} only has {lines} lines",
+                rel(&path)
+            );
+
+            md::Document::parse(&rel(&path), &raw, line_range(&raw, start, end))
+        } else {
+            let (_, content) = unwrap!(md::take_front_matter(&raw), "invalid fm in {}", rel(&path));
+            let doc = md::Document::parse(&rel(&path), &raw, raw.len() - content.len()..raw.len());
+
+            match directive.section {
+                Some(id) => unwrap!(section(doc, id), "{file}: bad include of {}", rel(&path)),
+                None => doc,
+            }
+        };
+
+        stack.push(path.clone());
+        let snippet = expand(snippet, &path, source, stack, included)?;
+        stack.pop();
+
+        included.push(path);
+
+        Ok(Some(snippet))
+    })?;
+
+    Ok(doc)
+}
+
+// byte range of lines `start..=end` (1 indexed)
+fn line_range(text: &str, start: usize, end: usize) -> Range<usize> {
+    let mut starts = std::iter::once(0).chain(text.match_indices('\n').map(|(i, _)| i + 1));
+
+    let first = starts.nth(start - 1).unwrap();
+    let last = starts.nth(end - start).unwrap_or(text.len());
+
+    first..last
+}
+
+// a heading and everything up to the next heading of the same or a higher level
+fn section(doc: md::Document, id: &str) -> Result<md::Document> {
+    let Some(section) = doc.sections().into_iter().find(|s| s.heading.id == id) else {
+        bail!("no heading with id {id:?}");
+    };
+
+    Ok(doc.slice(section.title.start..section.end))
+}
@@ -0,0 +1,98 @@
+use crate::alias::Aliases;
+use crate::md;
+use crate::nav::{NavFolder, NavPage};
+use crate::path::Path;
+use crate::source::Source;
+use log::{error, log, trace, Level};
+use std::collections::HashSet;
+
+// checks every relative link to an .md file
+//
+// links in included content are relative to the included file, and are reported there
+// (once per file, even if it's included by several pages)
+//
+// links to an alias are accepted with a warning (fix points them at the canonical path, see build_pages),
+// which is an error in strict mode
+//
+// returns how many links were broken, and how many pointed to an alias
+pub fn check_links(
+    root: &NavFolder,
+    source: &Source,
+    aliases: &Aliases,
+    strict: bool,
+) -> (usize, usize) {
+    let mut nav_paths = HashSet::new();
+
+    root.for_each_page(&mut |p| {
+        nav_paths.insert(p.path.normalize());
+    });
+
+    let level = if strict { Level::Error } else { Level::Warn };
+    let mut broken = 0;
+    let mut aliased = 0;
+    let mut checked = HashSet::new();
+
+    root.for_each_page(&mut |page| {
+        for (file, dest) in page_links(page, &mut checked, false) {
+            let dir = file.parent().unwrap().normalize();
+            let file = source.show(&file);
+
+            let page_link = md::split_link(dest).filter(|(path, _)| path.ends_with(".md"));
+            let Some((path, _)) = page_link else {
+                trace!(target: "check_links", "skipping {dest}");
+                continue;
+            };
+
+            let target = dir.join(&*md::decode_path(path)).normalize();
+
+            if nav_paths.contains(&target) {
+                continue;
+            }
+
+            if let Some(canonical) = alias_link(aliases, &dir, dest) {
+                log!(
+                    target: "check_links",
+                    level,
+                    "{file}: link {dest:?} points to an alias, use {canonical}",
+                );
+                aliased += 1;
+            } else {
+                error!(target: "check_links", "{file}: broken link {dest:?}");
+                broken += 1;
+            }
+        }
+    });
+
+    (broken, aliased)
+}
+
+/// (file it's relative to, dest) of every link in `page` (and image, if `images`),
+/// including the ones in included content that aren't in `checked` yet
+pub fn page_links<'p>(
+    page: &'p NavPage,
+    checked: &mut HashSet<(Path, &'p str)>,
+    images: bool,
+) -> Vec<(Path, &'p str)> {
+    let images = if images { &page.images[..] } else { &[] };
+    let mut links: Vec<_> = images
+        .iter()
+        .chain(&page.links)
+        .map(|dest| (page.path.clone(), dest.as_str()))
+        .collect();
+
+    for (file, dest) in &page.included_links {
+        if checked.insert((file.clone(), dest)) {
+            links.push((file.clone(), dest));
+        }
+    }
+
+    links
+}
+
+/// the link to the canonical page, if `dest` (in a page in `dir`) links to an alias
+pub fn alias_link(aliases: &Aliases, dir: &Path, dest: &str) -> Option<String> {
+    let (path, fragment) = md::split_link(dest)?;
+    let canonical = aliases.get(&dir.join(&*md::decode_path(path)).normalize())?;
+
+    Some(format!("{}{fragment}", canonical.relative_from(dir)))
+}
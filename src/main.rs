@@ -1,10 +1,20 @@
 #![forbid(unsafe_code)]
 
-use crate::config::{Config, ConfigFile, Mode};
+use crate::alias::{collect_aliases, Aliases};
+use crate::assets::{check_assets, AssetCheck};
+use crate::cache::Cache;
+use crate::config::{Config, ConfigFile, CopyAssets, Mode, NavFormat};
 use crate::dircheck::{dir_check, DirCheck};
-use crate::modes::{mode_build, mode_check, mode_fix};
+use crate::glossary::collect_glossary;
+use crate::links::check_links;
+use crate::modes::{
+    mode_build, mode_check, mode_diff, mode_extract, mode_fix, mode_init, mode_move, mode_nav,
+    mode_new,
+};
 use crate::path::Path;
-use crate::process::process_folder;
+use crate::process::{build_pages, resolve_root};
+use crate::source::Source;
+use crate::xref::check_xrefs;
 use anyhow::{bail, ensure, Context, Result};
 use log::{debug, error, info, warn, LevelFilter};
 use semver::Version;
@@ -22,15 +32,37 @@ macro_rules! unwrap {
     };
 }
 
+mod alias;
+mod assets;
+mod cache;
+mod code;
 mod config;
+mod diff;
 mod dircheck;
+mod docusaurus;
+mod extract;
 mod front_matter;
+mod glossary;
+mod grammar;
+mod include;
+mod links;
 mod md;
+mod mdbook;
 mod mkdocs;
 mod modes;
+mod mv;
 mod nav;
+mod navtree;
+mod nrignore;
+mod numbering;
 mod path;
 mod process;
+mod requirements;
+mod scaffold;
+mod source;
+mod toc;
+mod versions;
+mod xref;
 
 const CONFIG_PATH: &str = "nr-spec-md.toml";
 
@@ -38,12 +70,21 @@ const HELP_MESSAGE: &str = r"
 This tool helps to build and validate the inertya specification
 https://github.com/inertya/nr-spec-md
 
-Usage: nr-spec-md [MODE]
+Usage: nr-spec-md [MODE] [ARGS] [--strict]
 
 Modes:
 b, build - Builds a mkdocs site into mkdocs/
 c, check - Checks that the markdown matches the style guide
 f, fix   - Fixes any style mistakes (will modify src/)
+e, extract - Extracts code examples with an id into examples/
+d, diff <old-src> <new-src> - Prints a markdown changelog between two versions of src/
+n, nav [--format tree|json] - Prints the resolved nav
+init - Creates nr-spec-md.toml and src/ for a new spec
+new <page> - Creates a page (relative to src/) and adds it to the nav
+mv <old> <new> [--alias] - Moves a page (relative to src/), and updates the nav and links to it
+
+Flags:
+--strict - Warnings (like unused files) fail check (see [strict] in the config)
 ";
 
 fn main() -> ExitCode {
@@ -76,7 +117,14 @@ fn run() -> Result<()> {
 
     info!(target: "nr-spec-md", "v{version}");
 
-    let config = get_config()?;
+    let (mode, strict) = get_args()?;
+
+    // init creates the config
+    if let Mode::Init = mode {
+        return mode_init(&Path::new(CONFIG_PATH));
+    }
+
+    let config = get_config(mode, strict)?;
 
     debug!(target: "", "{config:#?}");
 
@@ -87,45 +135,218 @@ fn run() -> Result<()> {
         config.file.version_req,
     );
 
+    // diff processes its own source dirs
+    if let Mode::Diff(old, new) = &config.mode {
+        return mode_diff(&Path::new(old), &Path::new(new), &config);
+    }
+
     let src = Path::new(&config.file.build.source);
 
-    // this will read and process every file (specified in index navs)
-    let root = process_folder(&src.join("index.md"), None)?;
+    let source = Source::load(&src, &config.file.build)?;
+    let mut cache = Cache::load();
+
+    // this will read every file (specified in index navs)
+    let mut root = resolve_root(&mut cache, &source)?;
+
+    // nav, new and mv don't care about aliases
+    let aliases = match &config.mode {
+        Mode::Nav(_) | Mode::New(_) | Mode::Move(..) => Aliases::default(),
+        _ => collect_aliases(&src, &root).context("alias error")?,
+    };
+
+    // and process them (fix points links to aliases at the canonical page)
+    let fix_aliases = matches!(config.mode, Mode::Fix).then_some(&aliases);
+    build_pages(
+        &mut root,
+        &mut cache,
+        &source,
+        config.mode.needs_docs(),
+        fix_aliases,
+    )?;
+
+    // check doesn't write anything (it's mostly run in ci, where the cache wouldn't be kept anyway)
+    if !matches!(config.mode, Mode::Check) {
+        if let Err(e) = cache.save() {
+            warn!(target: "cache", "couldn't save the cache: {e:#}");
+        }
+    }
+
+    // nav, new and mv only need the resolved nav
+    match &config.mode {
+        Mode::Nav(format) => return mode_nav(&root, &source, *format),
+        Mode::New(page) => return mode_new(&root, &source, page),
+        Mode::Move(old, new, alias) => return mode_move(&root, &source, old, new, *alias),
+        _ => {}
+    }
 
     // print unused files
-    let DirCheck { unused, extra } = dir_check(&src, &root).context("dir check error")?;
+    let DirCheck {
+        unused,
+        extra,
+        walked_twice,
+    } = dir_check(&source, &root).context("dir check error")?;
 
-    unused
-        .iter()
-        .for_each(|path| warn!(target: "dir_check", "Unused Markdown File: {path}"));
+    // warnings that are errors in strict mode
+    let mut strict_errors = report_unused(
+        &config,
+        &source,
+        "dir_check",
+        "Unused Markdown File",
+        &unused,
+    );
+    strict_errors += report_unused(
+        &config,
+        &source,
+        "dir_check",
+        "Dir Already Walked (symlink loop?)",
+        &walked_twice,
+    );
+
+    let (broken, aliased) = check_links(&root, &source, &aliases, config.strict_check());
+    ensure!(broken == 0, "found {broken} broken links");
+    if config.strict_check() {
+        strict_errors += aliased;
+    }
+
+    let AssetCheck {
+        referenced,
+        unreferenced,
+        missing,
+    } = check_assets(&root, &source, &extra);
+    ensure!(missing == 0, "found {missing} missing assets");
+
+    strict_errors += report_unused(
+        &config,
+        &source,
+        "check_assets",
+        "Unreferenced Asset",
+        &unreferenced,
+    );
+
+    let extra = match config.file.build.copy_assets {
+        CopyAssets::All => extra,
+        CopyAssets::Referenced => extra
+            .into_iter()
+            .filter(|path| referenced.contains(&path.normalize()))
+            .collect(),
+    };
+
+    let (xrefs, xref_errors) = check_xrefs(&root);
+    ensure!(
+        xref_errors == 0,
+        "found {xref_errors} cross reference errors"
+    );
+
+    let glossary = collect_glossary(&root).context("glossary error")?;
 
     //
 
     match config.mode {
-        Mode::Build => mode_build(root, &config, &extra),
-        Mode::Check => mode_check(&root),
+        Mode::Build => mode_build(root, &config, &extra, &aliases, &xrefs, &glossary),
+        Mode::Check => mode_check(&root, &config, &glossary, strict_errors),
         Mode::Fix => mode_fix(&root),
+        Mode::Extract => mode_extract(&root, &config),
+        Mode::Diff(..) | Mode::Nav(_) | Mode::Init | Mode::New(_) | Mode::Move(..) => {
+            unreachable!()
+        }
     }
 }
 
-fn get_config() -> Result<Config> {
+// unused (or skipped) files are warnings, or errors when checking in strict mode (unless they're allowed)
+//
+// returns the number of errors
+fn report_unused(
+    config: &Config,
+    source: &Source,
+    target: &str,
+    what: &str,
+    paths: &[Path],
+) -> usize {
+    let strict = config.strict_check();
+
+    let allowed = config
+        .file
+        .strict
+        .allow
+        .iter()
+        .map(|a| source.dir.join(a).normalize())
+        .collect::<Vec<_>>();
+
+    let mut errors = 0;
+
+    for path in paths {
+        let normalized = path.normalize();
+        let shown = source.show(path);
+
+        if allowed.iter().any(|a| normalized.strip_prefix(a).is_some()) {
+            debug!(target: target, "allowed {what}: {shown}");
+        } else if strict {
+            error!(target: target, "{what}: {shown}");
+            errors += 1;
+        } else {
+            warn!(target: target, "{what}: {shown}");
+        }
+    }
+
+    errors
+}
+
+// (mode, --strict)
+fn get_args() -> Result<(Mode, bool)> {
+    let mut strict = false;
+    let args = env::args().skip(1).filter(|arg| {
+        let flag = arg == "--strict";
+        strict |= flag;
+        !flag
+    });
+
     // read mode before config to allow --help with no config file
-    let mode = get_mode()?;
+    let mode = get_mode(args)?;
 
+    Ok((mode, strict))
+}
+
+fn get_config(mode: Mode, strict: bool) -> Result<Config> {
     let config_str = fs::read_to_string(CONFIG_PATH).context("could not open config file")?;
     let config_file: ConfigFile = toml::from_str(&config_str).context("invalid config")?;
 
     Ok(Config {
+        strict: strict || config_file.strict.enabled,
         file: config_file,
         mode,
     })
 }
 
-fn get_mode() -> Result<Mode> {
-    let mode = match env::args().nth(1).as_deref() {
+fn get_mode(args: impl Iterator<Item = String>) -> Result<Mode> {
+    let mut args = args.peekable();
+
+    let mode = match args.next().as_deref() {
         Some("b" | "build") => Mode::Build,
         Some("c" | "check") => Mode::Check,
         Some("f" | "fix") => Mode::Fix,
+        Some("e" | "extract") => Mode::Extract,
+        Some("d" | "diff") => match (args.next(), args.next()) {
+            (Some(old), Some(new)) => Mode::Diff(old, new),
+            _ => bail!("Usage: nr-spec-md diff <old-src> <new-src>"),
+        },
+        Some("n" | "nav") => match (args.next().as_deref(), args.next().as_deref()) {
+            (None, _) => Mode::Nav(NavFormat::Tree),
+            (Some("--format"), Some("tree")) => Mode::Nav(NavFormat::Tree),
+            (Some("--format"), Some("json")) => Mode::Nav(NavFormat::Json),
+            _ => bail!("Usage: nr-spec-md nav [--format tree|json]"),
+        },
+        Some("init") => Mode::Init,
+        Some("new") => match args.next() {
+            Some(page) => Mode::New(page),
+            None => bail!("Usage: nr-spec-md new <page>"),
+        },
+        Some("mv") => match (args.next(), args.next()) {
+            (Some(old), Some(new)) => {
+                let alias = args.next_if(|arg| arg == "--alias").is_some();
+                Mode::Move(old, new, alias)
+            }
+            _ => bail!("Usage: nr-spec-md mv <old> <new> [--alias]"),
+        },
         None | Some("help" | "--help") => {
             eprintln!("{}", HELP_MESSAGE.trim());
             std::process::exit(2);
@@ -133,5 +354,9 @@ fn get_mode() -> Result<Mode> {
         Some(s) => bail!("Unknown mode {s:?}, try `nr-spec-md help`"),
     };
 
+    if let Some(arg) = args.next() {
+        bail!("Unexpected argument {arg:?}, try `nr-spec-md help`");
+    }
+
     Ok(mode)
 }
@@ -1,8 +1,17 @@
-use crate::front_matter::FrontMatter;
-use anyhow::{bail, Result};
+use crate::front_matter::{canonicalize_nav_lines, FrontMatter};
+use crate::include;
+use crate::path::Path;
+use crate::source::Source;
+use anyhow::{bail, ensure, Result};
 use log::trace;
-use pulldown_cmark::{Event, HeadingLevel, Options as CmOptions, Parser, Tag};
+use pulldown_cmark::{
+    CodeBlockKind, CowStr, Event, HeadingLevel, Options as CmOptions, Parser, Tag,
+};
 use pulldown_cmark_to_cmark::Options as C2cOptions;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::ops::Range;
 
 const MD_OPTIONS: CmOptions = CmOptions::ENABLE_TABLES
     .union(CmOptions::ENABLE_FOOTNOTES)
@@ -29,34 +38,157 @@ const C2C_OPTIONS: C2cOptions = C2cOptions {
 
 // transform nr-specific syntax to mkdocs syntax
 // specifically for admonitions and stuff
-pub fn build(content: &str) -> String {
-    // adapt(content, |p| {
-    //     yield;
-    // })
-    content.to_string()
-}
-
-pub fn fix(content: &str) -> String {
-    // convert to events and then back to a string
-    // easiest way to get a consistent style
-    adapt(content, |p| p)
-}
-
-// pull-push-pull :|
-// this is just a convince to avoid all the options and other boilerplate
-fn adapt<'a, I>(content: &'a str, f: impl FnOnce(Parser<'a, 'a>) -> I) -> String
-where
-    I: IntoIterator<Item = Event<'a>>,
-{
-    let parser = Parser::new_ext(content, MD_OPTIONS);
-    // decent guess for cap to try and avoid reallocating
-    let mut buf = String::with_capacity(content.len().next_power_of_two());
+//
+// also expands includes, returning every file that was included
+pub fn build(doc: Document, path: &Path, source: &Source) -> Result<(Document, Vec<Path>)> {
+    let mut included = Vec::new();
+    let doc = include::expand(
+        doc,
+        path,
+        source,
+        &mut vec![path.normalize()],
+        &mut included,
+    )?;
+
+    Ok((doc, included))
+}
+
+// fix, but also replaces the destination of any link (or image) that `f` returns Some for
+pub fn fix_links(content: &str, f: impl FnMut(&str) -> Option<String>) -> String {
+    write(fix_events(parse(content), f), content.len())
+}
 
-    let iter = f(parser).into_iter();
+fn fix_events<'a>(
+    events: impl IntoIterator<Item = Event<'a>>,
+    mut f: impl FnMut(&str) -> Option<String>,
+) -> impl Iterator<Item = Event<'a>> {
+    let events = map_xrefs(events, |id| {
+        // c2c would escape the brackets in text, but writes html as is
+        // the empty text stops c2c from adding a newline after the "html"
+        vec![
+            Event::Html(format!("[[{id}]]").into()),
+            Event::Text("".into()),
+        ]
+    });
+
+    events.into_iter().map(move |e| match e {
+        // c2c writes the url at the end tag, so both have to be changed
+        Event::Start(Tag::Link(ty, dest, title)) => {
+            let dest = f(&dest).map_or(dest, CowStr::from);
+            Event::Start(Tag::Link(ty, dest, title))
+        }
+        Event::End(Tag::Link(ty, dest, title)) => {
+            let dest = f(&dest).map_or(dest, CowStr::from);
+            Event::End(Tag::Link(ty, dest, title))
+        }
+        Event::Start(Tag::Image(ty, dest, title)) => {
+            let dest = f(&dest).map_or(dest, CowStr::from);
+            Event::Start(Tag::Image(ty, dest, title))
+        }
+        Event::End(Tag::Image(ty, dest, title)) => {
+            let dest = f(&dest).map_or(dest, CowStr::from);
+            Event::End(Tag::Image(ty, dest, title))
+        }
+        e => e,
+    })
+}
+
+// replaces every `[[id]]` cross reference with the events `f` returns
+pub fn map_xrefs<'a>(
+    events: impl IntoIterator<Item = Event<'a>>,
+    mut f: impl FnMut(&str) -> Vec<Event<'a>>,
+) -> Vec<Event<'a>> {
+    let mut out = Vec::new();
+
+    for e in events {
+        out.push(e);
+
+        // pulldown splits `[[id]]` into 5 text events: `[`, `[`, `id`, `]`, `]`
+        if let [.., Event::Text(a), Event::Text(b), Event::Text(id), Event::Text(c), Event::Text(d)] =
+            &out[..]
+        {
+            if [a, b, c, d].map(|s| &**s) == ["[", "[", "]", "]"] && is_xref_id(id) {
+                let id = id.to_string();
+                out.truncate(out.len() - 5);
+                out.extend(f(&id));
+            }
+        }
+    }
+
+    out
+}
+
+fn is_xref_id(id: &str) -> bool {
+    !id.is_empty()
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | ':'))
+}
+
+// `<!-- nr-name args -->` (`<!-- nr-name: args -->` works too)
+// returns (name, args)
+pub fn directive(html: &str) -> Option<(&str, &str)> {
+    let inner = html
+        .trim()
+        .strip_prefix("<!--")?
+        .strip_suffix("-->")?
+        .trim()
+        .strip_prefix("nr-")?;
+
+    let end = inner
+        .find(|c: char| c == ':' || c.is_whitespace())
+        .unwrap_or(inner.len());
+    let (name, args) = inner.split_at(end);
+    let args = args.strip_prefix(':').unwrap_or(args).trim();
+
+    Some((name, args))
+}
+
+// whether an event after `e` is inline (in the same paragraph, heading, etc.)
+fn continues_inline(e: &Owned) -> bool {
+    match e {
+        Owned::Heading { end, .. } => !end,
+        Owned::Event(e) => match e {
+            Event::Text(_)
+            | Event::Code(_)
+            | Event::SoftBreak
+            | Event::HardBreak
+            | Event::FootnoteReference(_)
+            | Event::TaskListMarker(_) => true,
+            // html blocks are whole lines
+            Event::Html(html) => !html.ends_with('\n'),
+            Event::Start(tag) => matches!(
+                tag,
+                Tag::Paragraph
+                    | Tag::TableCell
+                    | Tag::Emphasis
+                    | Tag::Strong
+                    | Tag::Strikethrough
+                    | Tag::Link(..)
+                    | Tag::Image(..)
+            ),
+            Event::End(tag) => matches!(
+                tag,
+                Tag::Emphasis | Tag::Strong | Tag::Strikethrough | Tag::Link(..) | Tag::Image(..)
+            ),
+            Event::Rule => false,
+        },
+    }
+}
+
+fn parse(content: &str) -> Parser<'_, '_> {
+    Parser::new_ext(content, MD_OPTIONS)
+}
+
+// `len` is the length of the original content
+fn write<'a>(events: impl IntoIterator<Item = Event<'a>>, len: usize) -> String {
+    // decent guess for cap to try and avoid reallocating
+    let mut buf = String::with_capacity(len.next_power_of_two());
 
     // error is a fmt::Error, which the string fmt::Write impl never returns, so unwrap should never panic
     // discarding the state cuz we dont need it
-    let _ = pulldown_cmark_to_cmark::cmark_with_options(iter, &mut buf, C2C_OPTIONS).unwrap();
+    let _ = pulldown_cmark_to_cmark::cmark_with_options(events.into_iter(), &mut buf, C2C_OPTIONS)
+        .unwrap();
 
     // pulldown c2c doesn't keep the trailing newline for some reason
     buf.push('\n');
@@ -64,29 +196,557 @@ where
     buf
 }
 
-pub fn extract_title_h1(content: &str) -> Result<String> {
-    let mut p = Parser::new_ext(content, MD_OPTIONS);
+/// a page's content parsed once, so everything that needs it (title, fixing, includes, headings, links,
+/// and every check and build pass) doesn't have to parse it again
+///
+/// it owns its events, so it's kept on the page (see NavPage::doc)
+#[derive(Debug, Default)]
+pub struct Document {
+    // the file that was parsed, then every file that was included into it (as shown in locations)
+    files: Vec<Path>,
+    events: Vec<(Owned, Option<Loc>)>,
+    // length of the content, to guess how long the written content will be
+    len: usize,
+}
+
+/// where an event was parsed from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Loc {
+    // in Document::files
+    file: usize,
+    // 1 indexed, of the whole file
+    pub line: usize,
+}
+
+impl Loc {
+    /// whether the event is from an included file (not the document's own file)
+    pub fn is_included(&self) -> bool {
+        self.file != 0
+    }
+}
+
+/// what later passes need to know about a page's built content (so they don't have to parse it again)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Outline {
+    pub headings: Vec<Heading>,
+    // ids of every `[[id]]` cross reference, in order
+    pub xrefs: Vec<String>,
+}
+
+// an event that doesn't borrow the content
+//
+// heading tags have their id and classes as &str (not CowStr), so they can't be 'static
+#[derive(Debug, Clone, PartialEq)]
+enum Owned {
+    Event(Event<'static>),
+    Heading {
+        end: bool,
+        level: HeadingLevel,
+        id: Option<String>,
+        classes: Vec<String>,
+    },
+}
 
-    match p.next() {
-        Some(Event::Start(Tag::Heading(HeadingLevel::H1, _, _))) => {}
-        Some(e) => bail!("expecting h1 heading, got: {e:?}"),
-        None => bail!("file is empty?"),
+impl Owned {
+    fn new(e: Event<'_>) -> Owned {
+        match &e {
+            Event::Start(Tag::Heading(level, id, classes))
+            | Event::End(Tag::Heading(level, id, classes)) => Owned::Heading {
+                end: matches!(e, Event::End(_)),
+                level: *level,
+                id: id.map(str::to_string),
+                classes: classes.iter().map(|c| c.to_string()).collect(),
+            },
+            e => Owned::Event(map_strs(e, |s| s.to_string().into())),
+        }
     }
 
-    let title = match p.next() {
-        Some(Event::Text(s) | Event::Code(s)) => s.into_string(),
-        e => unreachable!("bug? non text/code element in heading: {e:?}"),
+    fn event(&self) -> Event<'_> {
+        match self {
+            Owned::Event(e) => map_strs(e, CowStr::Borrowed),
+            Owned::Heading {
+                end,
+                level,
+                id,
+                classes,
+            } => {
+                let classes = classes.iter().map(String::as_str).collect();
+                let tag = Tag::Heading(*level, id.as_deref(), classes);
+
+                if *end {
+                    Event::End(tag)
+                } else {
+                    Event::Start(tag)
+                }
+            }
+        }
+    }
+}
+
+// the same event with every string replaced by `f` (headings are handled by Owned)
+fn map_strs<'a, 'b>(e: &'a Event<'_>, f: impl Fn(&'a str) -> CowStr<'b>) -> Event<'b> {
+    let tag = |tag: &'a Tag<'_>| match tag {
+        Tag::Paragraph => Tag::Paragraph,
+        Tag::Heading(..) => unreachable!("bug? heading in map_strs"),
+        Tag::BlockQuote => Tag::BlockQuote,
+        Tag::CodeBlock(CodeBlockKind::Indented) => Tag::CodeBlock(CodeBlockKind::Indented),
+        Tag::CodeBlock(CodeBlockKind::Fenced(info)) => {
+            Tag::CodeBlock(CodeBlockKind::Fenced(f(info)))
+        }
+        Tag::List(start) => Tag::List(*start),
+        Tag::Item => Tag::Item,
+        Tag::FootnoteDefinition(label) => Tag::FootnoteDefinition(f(label)),
+        Tag::Table(alignments) => Tag::Table(alignments.clone()),
+        Tag::TableHead => Tag::TableHead,
+        Tag::TableRow => Tag::TableRow,
+        Tag::TableCell => Tag::TableCell,
+        Tag::Emphasis => Tag::Emphasis,
+        Tag::Strong => Tag::Strong,
+        Tag::Strikethrough => Tag::Strikethrough,
+        Tag::Link(ty, dest, title) => Tag::Link(*ty, f(dest), f(title)),
+        Tag::Image(ty, dest, title) => Tag::Image(*ty, f(dest), f(title)),
     };
 
-    assert!(
-        matches!(
-            p.next(),
-            Some(Event::End(Tag::Heading(HeadingLevel::H1, _, _)))
-        ),
-        "bug? heading hasn't ended"
-    );
+    match e {
+        Event::Start(t) => Event::Start(tag(t)),
+        Event::End(t) => Event::End(tag(t)),
+        Event::Text(s) => Event::Text(f(s)),
+        Event::Code(s) => Event::Code(f(s)),
+        Event::Html(s) => Event::Html(f(s)),
+        Event::FootnoteReference(s) => Event::FootnoteReference(f(s)),
+        Event::SoftBreak => Event::SoftBreak,
+        Event::HardBreak => Event::HardBreak,
+        Event::Rule => Event::Rule,
+        Event::TaskListMarker(checked) => Event::TaskListMarker(*checked),
+    }
+}
+
+impl Document {
+    /// parses `text[range]`, where `text` is the whole file at `path` (so lines are of the whole file)
+    ///
+    /// `path` is only used to show locations, so it should be relative to the source dir
+    pub fn parse(path: &Path, text: &str, range: Range<usize>) -> Document {
+        let newlines = text[..range.end]
+            .match_indices('\n')
+            .map(|(i, _)| i)
+            .collect::<Vec<_>>();
+        let line = |offset: usize| newlines.partition_point(|i| *i < offset) + 1;
+
+        let events = parse(&text[range.clone()])
+            .into_offset_iter()
+            .map(|(e, r)| {
+                let loc = Loc {
+                    file: 0,
+                    line: line(range.start + r.start),
+                };
+                (Owned::new(e), Some(loc))
+            })
+            .collect();
+
+        Document {
+            files: vec![path.clone()],
+            events,
+            len: range.len(),
+        }
+    }
+
+    /// parses markdown that isn't from a file, so none of its events have a location
+    pub fn generated(content: &str) -> Document {
+        Document {
+            files: Vec::new(),
+            events: parse(content).map(|e| (Owned::new(e), None)).collect(),
+            len: content.len(),
+        }
+    }
+
+    pub fn events(&self) -> impl Iterator<Item = Event<'_>> + '_ {
+        self.events.iter().map(|(e, _)| e.event())
+    }
+
+    /// `file:line` of a location in this document
+    pub fn location(&self, loc: Loc) -> String {
+        format!("{}:{}", self.files[loc.file], loc.line)
+    }
+
+    /// events and where they're from (None if they were generated)
+    pub fn located_events(&self) -> impl Iterator<Item = (Event<'_>, Option<Loc>)> + '_ {
+        self.events.iter().map(|(e, loc)| (e.event(), *loc))
+    }
+
+    /// replaces the events with whatever `f` returns (which don't have a location)
+    pub fn transform(&mut self, f: impl for<'e> FnOnce(Vec<Event<'e>>) -> Vec<Event<'e>>) {
+        let events = f(self.events().collect())
+            .into_iter()
+            .map(|e| (Owned::new(e), None))
+            .collect();
+
+        self.events = events;
+    }
+
+    /// replaces every directive (see `directive`) with the events of the document `f` returns
+    /// (None keeps it as is)
+    ///
+    /// directives have to be on their own line, ones in a paragraph (or heading, etc.) are an error
+    pub fn replace_directives(
+        &mut self,
+        mut f: impl FnMut(&str, &str) -> Result<Option<Document>>,
+    ) -> Result<()> {
+        let mut events: Vec<(Owned, Option<Loc>)> = Vec::with_capacity(self.events.len());
+
+        for (e, loc) in std::mem::take(&mut self.events) {
+            let replacement = match &e {
+                Owned::Event(Event::Html(html)) => match directive(html) {
+                    // an inline directive would be replaced with blocks nested in a paragraph (or heading, etc.)
+                    Some(_) if events.last().is_some_and(|(e, _)| continues_inline(e)) => {
+                        let at =
+                            loc.map_or_else(String::new, |loc| format!("{}: ", self.location(loc)));
+                        bail!("{at}{:?} has to be on its own line", html.trim());
+                    }
+                    Some((name, args)) => f(name, args)?,
+                    None => None,
+                },
+                _ => None,
+            };
+
+            let Some(doc) = replacement else {
+                events.push((e, loc));
+                continue;
+            };
+
+            // the replacement's files come after ours
+            let offset = self.files.len();
+            self.files.extend(doc.files);
+            self.len += doc.len;
+
+            events.extend(doc.events.into_iter().map(|(e, loc)| {
+                let loc = loc.map(|loc| Loc {
+                    file: loc.file + offset,
+                    ..loc
+                });
+                (e, loc)
+            }));
+        }
+
+        self.events = events;
+
+        Ok(())
+    }
+
+    /// only keeps the events in `range` (indexes into `events`)
+    pub fn slice(mut self, range: Range<usize>) -> Document {
+        self.events.truncate(range.end);
+        self.events.drain(..range.start);
+        self
+    }
+
+    /// sets the id of every heading, in order (eg. to make them explicit)
+    ///
+    /// there has to be an id for every heading
+    pub fn set_heading_ids(&mut self, ids: &[String]) -> Result<()> {
+        let mut ids = ids.iter();
+        let mut current = None;
+
+        for (e, _) in &mut self.events {
+            let Owned::Heading { end, id, .. } = e else {
+                continue;
+            };
+
+            if !*end {
+                let Some(new) = ids.next() else {
+                    bail!("more headings than ids");
+                };
+                current = Some(new);
+            }
+
+            *id = current.cloned();
+        }
+
+        ensure!(ids.next().is_none(), "more ids than headings");
+
+        Ok(())
+    }
+
+    /// the content (with every change made to it)
+    pub fn write(&self) -> String {
+        write(self.events(), self.len)
+    }
+
+    pub fn title_h1(&self) -> Result<String> {
+        let mut events = self.events();
+
+        match events.next() {
+            Some(Event::Start(Tag::Heading(HeadingLevel::H1, _, _))) => {}
+            Some(e) => bail!("expecting h1 heading, got: {e:?}"),
+            None => bail!("file is empty?"),
+        }
+
+        let title = match events.next() {
+            Some(Event::Text(s) | Event::Code(s)) => s.into_string(),
+            e => unreachable!("bug? non text/code element in heading: {e:?}"),
+        };
+
+        assert!(
+            matches!(
+                events.next(),
+                Some(Event::End(Tag::Heading(HeadingLevel::H1, _, _)))
+            ),
+            "bug? heading hasn't ended"
+        );
 
-    Ok(title)
+        Ok(title)
+    }
+
+    pub fn fix(&self) -> String {
+        self.fix_links(|_| None)
+    }
+
+    /// like fix, but also replaces the destination of any link (or image) that `f` returns Some for
+    pub fn fix_links(&self, f: impl FnMut(&str) -> Option<String>) -> String {
+        // convert to events and then back to a string
+        // easiest way to get a consistent style
+        write(fix_events(self.events(), f), self.len)
+    }
+
+    // destinations of every link, in order
+    pub fn links(&self) -> Vec<String> {
+        self.events()
+            .filter_map(|e| match e {
+                Event::Start(Tag::Link(_, dest, _)) => Some(dest.to_string()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    pub fn images(&self) -> Vec<String> {
+        self.events()
+            .filter_map(|e| match e {
+                Event::Start(Tag::Image(_, dest, _)) => Some(dest.to_string()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// (file, dest) of every link and image from an included file, in order
+    ///
+    /// the file is relative to the source dir (like in locations)
+    pub fn included_links(&self) -> Vec<(Path, String)> {
+        self.located_events()
+            .filter_map(|(e, loc)| {
+                let loc = loc.filter(Loc::is_included)?;
+                match e {
+                    Event::Start(Tag::Link(_, dest, _) | Tag::Image(_, dest, _)) => {
+                        Some((self.files[loc.file].clone(), dest.to_string()))
+                    }
+                    _ => None,
+                }
+            })
+            .collect()
+    }
+
+    // every heading, in order
+    pub fn headings(&self) -> Vec<Heading> {
+        headings_of(self.events())
+    }
+
+    /// where every heading's section is in the events, in order
+    pub fn sections(&self) -> Vec<SectionRange> {
+        // (level, start, end) of every heading
+        let mut ranges = Vec::new();
+        for (i, e) in self.events().enumerate() {
+            match e {
+                Event::Start(Tag::Heading(level, ..)) => ranges.push((level, i, i)),
+                Event::End(Tag::Heading(..)) => ranges.last_mut().unwrap().2 = i + 1,
+                _ => {}
+            }
+        }
+
+        // same order as ranges
+        self.headings()
+            .into_iter()
+            .enumerate()
+            .map(|(i, heading)| {
+                let (level, start, end) = ranges[i];
+                let section_end = ranges[i + 1..]
+                    .iter()
+                    .find(|(l, ..)| *l <= level)
+                    .map_or(self.events.len(), |(_, start, _)| *start);
+
+                SectionRange {
+                    heading,
+                    title: start..end,
+                    end: section_end,
+                }
+            })
+            .collect()
+    }
+
+    pub fn outline(&self) -> Outline {
+        let mut xrefs = Vec::new();
+
+        map_xrefs(self.events(), |id| {
+            xrefs.push(id.to_string());
+            Vec::new()
+        });
+
+        Outline {
+            headings: self.headings(),
+            xrefs,
+        }
+    }
+}
+
+/// where a heading's section is in a document's events
+#[derive(Debug, Clone)]
+pub struct SectionRange {
+    pub heading: Heading,
+    // the heading's own events
+    pub title: Range<usize>,
+    // the next heading of the same or a higher level (so subsections are included)
+    pub end: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Heading {
+    pub level: HeadingLevel,
+    // explicit `{#id}` if there is one, otherwise the id mkdocs would generate
+    pub id: String,
+    pub explicit_id: bool,
+    pub text: String,
+}
+
+// the heading something is under
+#[derive(Debug, Clone)]
+pub struct Section {
+    pub id: String,
+    pub title: String,
+}
+
+fn headings_of<'a>(events: impl IntoIterator<Item = Event<'a>>) -> Vec<Heading> {
+    let mut headings = Vec::new();
+    let mut used_ids = HashSet::new();
+    let mut current = None;
+
+    for e in events {
+        match e {
+            Event::Start(Tag::Heading(level, id, _)) => current = Some((level, id, String::new())),
+            Event::Text(s) | Event::Code(s) => {
+                if let Some((_, _, text)) = &mut current {
+                    text.push_str(&s);
+                }
+            }
+            Event::End(Tag::Heading(..)) => {
+                let (level, id, text) = current.take().unwrap();
+                let explicit_id = id.is_some();
+                let id = match id {
+                    Some(id) => id.to_string(),
+                    None => unique_id(slugify(&text), &used_ids),
+                };
+
+                used_ids.insert(id.clone());
+                headings.push(Heading {
+                    level,
+                    id,
+                    explicit_id,
+                    text,
+                });
+            }
+            _ => {}
+        }
+    }
+
+    headings
+}
+
+// same as python-markdown's toc slugify (which mkdocs uses)
+fn slugify(text: &str) -> String {
+    let text: String = text
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-') || c.is_whitespace())
+        .collect();
+
+    let mut slug = String::with_capacity(text.len());
+
+    for c in text.trim().chars() {
+        if c == '-' || c.is_whitespace() {
+            if !slug.ends_with('-') {
+                slug.push('-');
+            }
+        } else {
+            slug.push(c.to_ascii_lowercase());
+        }
+    }
+
+    slug
+}
+
+// python-markdown appends _1, _2, etc. to duplicate ids
+fn unique_id(id: String, used: &HashSet<String>) -> String {
+    if !id.is_empty() && !used.contains(&id) {
+        return id;
+    }
+
+    (1..)
+        .map(|n| format!("{id}_{n}"))
+        .find(|id| !used.contains(id))
+        .unwrap()
+}
+
+/// splits a relative link into (path, `#fragment` or `?query`), eg. `dir/page.md#frag` -> (`dir/page.md`, `#frag`)
+///
+/// None for urls (anything with a scheme, eg. `https:`, `data:`, `tel:`), anchors, and absolute paths
+pub fn split_link(dest: &str) -> Option<(&str, &str)> {
+    if has_scheme(dest) || dest.starts_with(['/', '#']) {
+        return None;
+    }
+
+    Some(match dest.find(['#', '?']) {
+        Some(i) => dest.split_at(i),
+        None => (dest, ""),
+    })
+}
+
+// `scheme:` at the start (rfc 3986), which makes it a url instead of a relative path
+fn has_scheme(dest: &str) -> bool {
+    dest.split_once(':').is_some_and(|(scheme, _)| {
+        scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+            && scheme
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+    })
+}
+
+/// decodes `%xx` escapes in a link's path (eg. `my%20file.pdf`), to find the file it points to
+///
+/// invalid escapes are kept as they are
+pub fn decode_path(path: &str) -> Cow<'_, str> {
+    if !path.contains('%') {
+        return Cow::Borrowed(path);
+    }
+
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let escape = bytes
+            .get(i + 1..i + 3)
+            .filter(|hex| bytes[i] == b'%' && hex.iter().all(u8::is_ascii_hexdigit));
+
+        match escape {
+            Some(hex) => {
+                let hex = std::str::from_utf8(hex).unwrap();
+                decoded.push(u8::from_str_radix(hex, 16).unwrap());
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+
+    // escapes that aren't utf-8 can't be a file name anyway
+    String::from_utf8(decoded).map_or(Cow::Borrowed(path), Cow::Owned)
 }
 
 pub fn take_front_matter(content: &str) -> Result<(Option<FrontMatter>, &str)> {
@@ -101,6 +761,49 @@ pub fn take_front_matter(content: &str) -> Result<(Option<FrontMatter>, &str)> {
     Ok((Some(serde_yaml::from_str(fm)?), remaining))
 }
 
+/// like prepend_front_matter, but keeps the fm as it's written in `raw` (comments, blank lines, order)
+/// except for nav entries, which are canonicalized
+///
+/// `fm` has to be the fm parsed from `raw`, otherwise it's just serialized
+pub fn fix_front_matter(raw: &str, fm: &FrontMatter, content: &str) -> String {
+    edit_front_matter(raw, fm, content, canonicalize_nav_lines)
+}
+
+/// like fix_front_matter, but `edit` changes the fm yaml (instead of canonicalizing nav entries)
+///
+/// `fm` is what the edited yaml should parse as, otherwise it's just serialized
+pub fn edit_front_matter(
+    raw: &str,
+    fm: &FrontMatter,
+    content: &str,
+    edit: impl FnOnce(&str) -> String,
+) -> String {
+    let original = raw
+        .strip_prefix("---\n")
+        .and_then(|s| s.split_once("\n---"))
+        .map(|(yaml, _)| yaml);
+
+    let Some(original) = original else {
+        return prepend_front_matter(fm, content);
+    };
+
+    let yaml = edit(original);
+
+    let value = serde_yaml::to_value(fm).unwrap();
+
+    // just in case a line was rewritten into something else
+    let same = serde_yaml::from_str::<FrontMatter>(&yaml)
+        .is_ok_and(|parsed| serde_yaml::to_value(parsed).unwrap() == value);
+
+    // empty fm blocks are removed by prepend_front_matter
+    if !same || value.as_mapping().is_some_and(|m| m.is_empty()) {
+        trace!(target: "fix_front_matter", "serializing fm");
+        return prepend_front_matter(fm, content);
+    }
+
+    format!("---\n{yaml}\n---\n{content}")
+}
+
 pub fn prepend_front_matter(fm: &FrontMatter, content: &str) -> String {
     let Ok(serde_yaml::Value::Mapping(map)) = serde_yaml::to_value(fm) else {
         panic!("ser error/fm not a map???");
@@ -121,3 +824,63 @@ pub fn prepend_front_matter(fm: &FrontMatter, content: &str) -> String {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_link_skips_urls() {
+        for dest in [
+            "https://example.com/a.md",
+            "mailto:a@example.com",
+            "tel:+123",
+            "data:image/gif;base64,R0lGODlh",
+            "/abs/a.png",
+            "#frag",
+        ] {
+            assert_eq!(split_link(dest), None, "{dest}");
+        }
+    }
+
+    #[test]
+    fn split_link_splits_relative_paths() {
+        assert_eq!(split_link("dir/a.md#frag"), Some(("dir/a.md", "#frag")));
+        assert_eq!(split_link("a.pdf?x=1"), Some(("a.pdf", "?x=1")));
+        assert_eq!(split_link("../a:b.png"), Some(("../a:b.png", "")));
+        assert_eq!(split_link("my%20file.pdf"), Some(("my%20file.pdf", "")));
+    }
+
+    #[test]
+    fn decode_path_escapes() {
+        assert_eq!(decode_path("my%20file.pdf"), "my file.pdf");
+        assert_eq!(decode_path("%C3%A9.png"), "é.png");
+        assert_eq!(decode_path("100%.png"), "100%.png");
+        assert_eq!(decode_path("%zz%2"), "%zz%2");
+        assert_eq!(decode_path("%FF.png"), "%FF.png");
+    }
+
+    #[test]
+    fn inline_directives_are_rejected() {
+        let replace = |text: &str| {
+            let mut doc = Document::parse(&Path::new("a.md"), text, 0..text.len());
+            doc.replace_directives(|_, _| {
+                Ok(Some(Document::parse(&Path::new("b.md"), "b\n", 0..2)))
+            })
+        };
+
+        assert!(replace("a\n\n<!-- nr-include: b.md -->\n").is_ok());
+        assert!(replace("a\n<!-- nr-include: b.md -->\n").is_ok());
+        assert!(replace("- <!-- nr-include: b.md -->\n- a\n").is_ok());
+
+        for text in [
+            "a <!-- nr-include: b.md --> c\n",
+            "- a <!-- nr-include: b.md -->\n",
+            "# a <!-- nr-include: b.md -->\n",
+            "| a |\n|---|\n| <!-- nr-include: b.md --> |\n",
+        ] {
+            let e = replace(text).unwrap_err().to_string();
+            assert!(e.starts_with("a.md:"), "{e}");
+        }
+    }
+}
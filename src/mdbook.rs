@@ -0,0 +1,104 @@
+use crate::config::Metadata;
+use crate::nav::{NavFolder, NavItem};
+use crate::path::Path;
+use serde::Serialize;
+use std::fmt::Write;
+
+// mdBook uses the same docs dir as mkdocs, so all paths here are relative to it
+//
+// categories become draft chapters (`- [Name]()`), which mdBook shows without a link
+
+#[derive(Serialize)]
+struct BookToml<'a> {
+    book: Book<'a>,
+}
+
+#[derive(Serialize)]
+struct Book<'a> {
+    title: &'a str,
+    description: &'a str,
+    src: &'a str,
+}
+
+/// generates book.toml, with `src` as the docs dir (relative to book.toml)
+pub fn book_toml(meta: &Metadata, src: &str) -> String {
+    let toml = BookToml {
+        book: Book {
+            title: &meta.name,
+            description: &meta.description,
+            src,
+        },
+    };
+
+    toml::to_string(&toml).unwrap()
+}
+
+/// generates SUMMARY.md
+///
+/// `doc_path` maps a page's path to its path in the docs dir
+///
+/// `generated` are (name, doc path) of generated pages to add after the nav
+pub fn summary_md(
+    root: &NavFolder,
+    doc_path: &impl Fn(&Path) -> Path,
+    generated: &[(&str, Path)],
+) -> String {
+    let mut out = String::from("# Summary\n\n");
+
+    // the root index is a prefix chapter (not numbered, and outside the list)
+    writeln!(
+        out,
+        "{}\n",
+        link(&root.index.name, &doc_path(&root.index.path))
+    )
+    .unwrap();
+
+    for item in &root.children {
+        write_item(&mut out, item, doc_path, 0);
+    }
+
+    if !generated.is_empty() {
+        out.push('\n');
+        for (name, path) in generated {
+            writeln!(out, "{}", link(name, path)).unwrap();
+        }
+    }
+
+    out
+}
+
+fn write_item(out: &mut String, item: &NavItem, doc_path: &impl Fn(&Path) -> Path, depth: usize) {
+    let indent = "    ".repeat(depth);
+
+    let (entry, children) = match item {
+        NavItem::Page(page) => (link(&page.name, &doc_path(&page.path)), &[][..]),
+        NavItem::Folder(folder) => (
+            link(&folder.index.name, &doc_path(&folder.index.path)),
+            &folder.children[..],
+        ),
+        NavItem::Category(category) => (
+            format!("[{}]()", escape(&category.name)),
+            &category.children[..],
+        ),
+    };
+
+    writeln!(out, "{indent}- {entry}").unwrap();
+
+    for child in children {
+        write_item(out, child, doc_path, depth + 1);
+    }
+}
+
+fn link(name: &str, path: &Path) -> String {
+    let path = path.to_string();
+
+    if path.contains(' ') {
+        format!("[{}](<{path}>)", escape(name))
+    } else {
+        format!("[{}]({path})", escape(name))
+    }
+}
+
+fn escape(name: &str) -> String {
+    name.replace('[', "\\[").replace(']', "\\]")
+}
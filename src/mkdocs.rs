@@ -1 +1,139 @@
+use crate::config::Metadata;
+use crate::front_matter::singleton_map;
+use crate::nav::{NavFolder, NavItem};
+use crate::path::Path;
+use serde::Serialize;
+use serde_yaml::{Mapping, Value};
+use std::collections::BTreeMap;
 
+// all paths here are relative to the docs dir (which is the same as relative to the source dir)
+
+#[derive(Serialize)]
+struct MkDocsYml<'a> {
+    site_name: &'a str,
+    site_description: &'a str,
+    copyright: &'a str,
+    nav: Vec<Value>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    plugins: Vec<Value>,
+    markdown_extensions: &'static [&'static str],
+}
+
+// match the features that md::MD_OPTIONS parses
+// attr_list is needed for `# Heading {#id}` (which numbered headings always use)
+const MARKDOWN_EXTENSIONS: &[&str] = &["tables", "footnotes", "attr_list"];
+
+/// generates mkdocs.yml
+///
+/// `doc_path` maps a page's path to its path in the docs dir
+///
+/// `generated` are (name, doc path) of generated pages to add to the end of the nav
+pub fn mkdocs_yml(
+    meta: &Metadata,
+    root: &NavFolder,
+    doc_path: &impl Fn(&Path) -> Path,
+    generated: &[(&str, Path)],
+    redirect_maps: BTreeMap<String, String>,
+) -> String {
+    let mut nav = vec![singleton_map(
+        root.index.name.as_str(),
+        doc_path(&root.index.path).to_string(),
+    )];
+    nav.extend(root.children.iter().map(|i| nav_item(i, doc_path)));
+    nav.extend(
+        generated
+            .iter()
+            .map(|(name, path)| singleton_map(*name, path.to_string())),
+    );
+
+    let mut plugins = Vec::new();
+
+    if !redirect_maps.is_empty() {
+        let mut redirects = Mapping::new();
+        redirects.insert("redirect_maps".into(), to_value(redirect_maps));
+
+        // specifying plugins disables the default search plugin
+        plugins.push("search".into());
+        plugins.push(singleton_map("redirects", redirects));
+    }
+
+    let yml = MkDocsYml {
+        site_name: &meta.name,
+        site_description: &meta.description,
+        copyright: &meta.copyright,
+        nav,
+        plugins,
+        markdown_extensions: MARKDOWN_EXTENSIONS,
+    };
+
+    serde_yaml::to_string(&yml).unwrap()
+}
+
+fn nav_item(item: &NavItem, doc_path: &impl Fn(&Path) -> Path) -> Value {
+    match item {
+        NavItem::Page(page) => singleton_map(page.name.as_str(), doc_path(&page.path).to_string()),
+        NavItem::Folder(folder) => {
+            // a section whose first page is the index
+            let mut children = vec![Value::String(doc_path(&folder.index.path).to_string())];
+            children.extend(folder.children.iter().map(|i| nav_item(i, doc_path)));
+
+            singleton_map(folder.index.name.as_str(), children)
+        }
+        NavItem::Category(category) => singleton_map(
+            category.name.as_str(),
+            category
+                .children
+                .iter()
+                .map(|i| nav_item(i, doc_path))
+                .collect::<Vec<_>>(),
+        ),
+    }
+}
+
+fn to_value(map: BTreeMap<String, String>) -> Value {
+    Value::Mapping(
+        map.into_iter()
+            .map(|(k, v)| (Value::String(k), Value::String(v)))
+            .collect(),
+    )
+}
+
+/// the url mkdocs serves a page at (with use_directory_urls)
+///
+/// `a/b.md` -> `a/b/`, `a/index.md` -> `a/`
+pub fn page_url(doc: &Path) -> String {
+    let doc = doc.to_string();
+    let stem = doc.strip_suffix(".md").unwrap_or(&doc);
+
+    match stem.strip_suffix("index") {
+        Some(dir) if dir.is_empty() || dir.ends_with('/') => dir.to_string(),
+        _ => format!("{stem}/"),
+    }
+}
+
+/// a stub page that redirects from `alias` to `page`
+///
+/// returns the path of the stub and its html
+pub fn redirect_page(alias: &Path, page: &Path) -> (Path, String) {
+    let alias_url = page_url(alias);
+    let up = "../".repeat(alias_url.matches('/').count());
+    let href = format!("{up}{}", page_url(page));
+
+    let html = format!(
+        r#"<!doctype html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>Redirecting...</title>
+<link rel="canonical" href="{href}">
+<meta http-equiv="refresh" content="0; url={href}">
+</head>
+<body>
+Redirecting to <a href="{href}">{href}</a>...
+</body>
+</html>
+"#
+    );
+
+    (Path::new(alias_url).join("index.html"), html)
+}
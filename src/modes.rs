@@ -1,21 +1,329 @@
-use crate::config::Config;
+use crate::alias::Aliases;
+use crate::cache::Cache;
+use crate::code::check_code_blocks;
+use crate::config::{Config, Export, NavFormat, Redirects};
+use crate::diff::changelog;
+use crate::dircheck::walk_dir_recursive;
+use crate::docusaurus::sidebars_json;
+use crate::extract::{collect_examples, manifest_json};
+use crate::glossary::{apply_glossary, check_glossary, Glossary};
+use crate::grammar::{apply_grammar, check_grammar, collect_grammar};
+use crate::mdbook::{book_toml, summary_md};
+use crate::mkdocs;
+use crate::mv::move_page;
 use crate::nav::NavFolder;
+use crate::navtree::{nav_json, nav_tree};
+use crate::numbering::{apply_numbers, check_section_refs, number_sections};
 use crate::path::Path;
-use anyhow::{anyhow, Result};
-use log::{debug, error, info};
-use std::fs::File;
+use crate::process::process_root;
+use crate::requirements::{
+    apply_requirement_ids, check_requirements, collect_requirements, register_json, register_md,
+};
+use crate::scaffold::{init, new_page};
+use crate::source::Source;
+use crate::toc::{apply_children, apply_toc, check_markers};
+use crate::versions::{
+    add_banner, add_version, banner_pages, check_version, read_versions, versions_json, LATEST,
+};
+use crate::xref::{apply_xrefs, XRefs};
+use anyhow::{anyhow, bail, ensure, Result};
+use log::{debug, error, info, trace};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::{self, File};
 use std::io::Write;
 
-pub fn mode_build(root: NavFolder, config: &Config, extra: &[Path]) -> Result<()> {
+pub fn mode_build(
+    mut root: NavFolder,
+    config: &Config,
+    extra: &[Path],
+    aliases: &Aliases,
+    xrefs: &XRefs,
+    glossary: &Glossary,
+) -> Result<()> {
     debug!(target: "mode", "build");
 
-    let _ = root;
-    let _ = config;
-    let _ = extra;
+    let src = Path::new(&config.file.build.source).normalize();
+    let output = Path::new(&config.file.build.output);
+
+    let mut versions = Vec::new();
+    let version = if config.file.build.versioned {
+        let Some(version) = config.file.metadata.spec_version.as_deref() else {
+            bail!("versioned builds need a metadata.spec_version");
+        };
+        check_version(version)?;
+
+        versions = read_versions(&output)?;
+        add_version(&mut versions, version);
+        Some(version)
+    } else {
+        None
+    };
+
+    let out = version.map_or_else(|| output.clone(), |version| output.join(version));
+    let docs = out.join("docs");
+
+    // (version, latest version) if this isn't the latest version, so its pages get a banner
+    let outdated = version
+        .map(|version| (version, versions[0].version.as_str()))
+        .filter(|(version, latest)| version != latest);
+
+    // everything that goes in the docs dir keeps its path relative to the source dir
+    let doc_path = |path: &Path| -> Path {
+        path.normalize()
+            .strip_prefix(&src)
+            .expect("bug? path isn't in the source dir")
+    };
+
+    let numbers = number_sections(&root);
+    let requirements = collect_requirements(&root);
+    let grammar = collect_grammar(&root);
+
+    apply_children(&mut root)?;
+
+    root.try_for_each_page_mut(&mut |page| {
+        unwrap!(apply_toc(page), "couldn't build toc for {}", page.rel_path);
+        apply_xrefs(page, xrefs);
+        apply_glossary(page, glossary);
+        apply_requirement_ids(page);
+        apply_grammar(page, &grammar, &doc_path);
+        unwrap!(
+            apply_numbers(page, &numbers, config.file.build.number_headings),
+            "couldn't number sections in {}",
+            page.rel_path
+        );
+        Ok(())
+    })?;
+
+    let mut outputs = Outputs::default();
+    let mut total = 0;
+
+    root.for_each_page(&mut |page| {
+        total += 1;
+
+        let doc = doc_path(&page.path);
+        let content = match outdated {
+            Some((version, latest)) => add_banner(&page.doc.write(), version, latest, &doc),
+            None => page.doc.write(),
+        };
+        outputs.write(&docs.join(doc), content);
+    });
+
+    for path in extra {
+        let content = unwrap!(fs::read(path), "couldn't read {path}");
+        outputs.write(&docs.join(doc_path(path)), &content);
+    }
+
+    let mut redirect_maps = BTreeMap::new();
+
+    for (alias, page) in aliases.iter() {
+        let (alias, page) = (doc_path(alias), doc_path(page));
+
+        match config.file.build.redirects {
+            Redirects::Html => {
+                let (stub, html) = mkdocs::redirect_page(&alias, &page);
+                let stub = docs.join(stub);
+
+                ensure!(
+                    !outputs.contains(&stub),
+                    "redirect for alias {alias} would overwrite {stub}"
+                );
+                outputs.write(&stub, &html);
+            }
+            Redirects::Mkdocs => {
+                redirect_maps.insert(alias.to_string(), page.to_string());
+            }
+        }
+    }
+
+    let mut generated = Vec::new();
+
+    if config.file.requirements.register {
+        let mut ids = HashSet::new();
+        for id in requirements.iter().filter_map(|r| r.id.as_ref()) {
+            ensure!(ids.insert(id), "duplicate requirement id {id:?}");
+        }
+
+        let number = |page: &Path, id: &str| {
+            numbers
+                .section(page, id)
+                .filter(|num| !num.is_empty())
+                .map(|num| num.to_string())
+        };
+
+        let md_path = Path::new("requirements.md");
+        let md = register_md(&requirements, &md_path, &doc_path, &number);
+        let json = register_json(&requirements, &doc_path, &number);
+
+        for name in [&md_path, &Path::new("requirements.json")] {
+            ensure!(
+                !outputs.contains(&docs.join(name)),
+                "generated requirements register would overwrite {name}"
+            );
+        }
+
+        outputs.write(&docs.join(&md_path), &md);
+        outputs.write(&docs.join("requirements.json"), &json);
+
+        generated.push(("Requirements", md_path));
+    }
+
+    let yml = mkdocs::mkdocs_yml(
+        &config.file.metadata,
+        &root,
+        &doc_path,
+        &generated,
+        redirect_maps,
+    );
+    outputs.write(&out.join("mkdocs.yml"), &yml);
+
+    for export in &config.file.build.exports {
+        match export {
+            Export::Mdbook => {
+                let summary = docs.join("SUMMARY.md");
+                ensure!(
+                    !outputs.contains(&summary),
+                    "generated SUMMARY.md would overwrite {summary}"
+                );
+                outputs.write(&summary, summary_md(&root, &doc_path, &generated));
+                outputs.write(
+                    &out.join("book.toml"),
+                    book_toml(&config.file.metadata, "docs"),
+                );
+            }
+            Export::Docusaurus => {
+                outputs.write(
+                    &out.join("sidebars.json"),
+                    sidebars_json(&root, &doc_path, &generated),
+                );
+            }
+        }
+    }
+
+    // stale dirs (removed pages shouldn't stick around)
+    let mut stale = vec![docs];
+
+    if let Some(version) = version {
+        outputs.write(&output.join("versions.json"), versions_json(&versions));
+
+        let latest = &versions[0].version;
+
+        if latest == version {
+            let latest_dir = output.join(LATEST);
+            for (path, content) in outputs.files_in(&out) {
+                outputs.write(&latest_dir.join(path), content);
+            }
+            stale.push(latest_dir);
+
+            // the banners of older versions need to point to the new latest version
+            for v in &versions[1..] {
+                let docs = output.join(&v.version).join("docs");
+                for (path, content) in banner_pages(&docs, &v.version, latest)? {
+                    outputs.write(&docs.join(path), content);
+                }
+            }
+        }
+    }
+
+    outputs.flush()?;
+    for dir in &stale {
+        outputs.remove_stale(dir)?;
+    }
+
+    info!(
+        target: "",
+        "Built {total} pages into {out} ({} files changed)",
+        outputs.changed
+    );
+
+    Ok(())
+}
+
+// only rewrites files that changed, so unchanged outputs keep their mtime
+// (and tools watching the output dir don't rebuild everything)
+//
+// files are kept until `flush`, so they can still be changed (eg. version banners) without being written twice
+#[derive(Default)]
+struct Outputs {
+    // normalized
+    files: HashMap<Path, Vec<u8>>,
+    changed: usize,
+}
+
+impl Outputs {
+    fn contains(&self, path: &Path) -> bool {
+        self.files.contains_key(&path.normalize())
+    }
+
+    fn write(&mut self, path: &Path, content: impl AsRef<[u8]>) {
+        self.files
+            .insert(path.normalize(), content.as_ref().to_vec());
+    }
+
+    // the files in `dir` (relative to it) and their content
+    fn files_in(&self, dir: &Path) -> Vec<(Path, Vec<u8>)> {
+        let dir = dir.normalize();
+
+        self.files
+            .iter()
+            .filter_map(|(path, content)| Some((path.strip_prefix(&dir)?, content.clone())))
+            .collect()
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        for (path, content) in &self.files {
+            if fs::read(path).is_ok_and(|old| old == *content) {
+                trace!(target: "outputs", "unchanged {path}");
+                continue;
+            }
+
+            create_parent_dir(path)?;
+            unwrap!(fs::write(path, content), "couldn't write {path}");
+            self.changed += 1;
+        }
+
+        Ok(())
+    }
+
+    // removes every file in dir that wasn't written by this build
+    fn remove_stale(&mut self, dir: &Path) -> Result<()> {
+        let mut stale = Vec::new();
+        walk_dir_recursive(dir, &mut |path| {
+            if !self.contains(&path) {
+                stale.push(path);
+            }
+        })?;
+
+        for path in stale {
+            debug!(target: "outputs", "removing {path}");
+            unwrap!(fs::remove_file(&path), "couldn't remove {path}");
+            self.changed += 1;
+        }
+
+        Ok(())
+    }
+}
+
+fn create_parent_dir(path: &Path) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        unwrap!(fs::create_dir_all(&dir), "couldn't create dir {dir}");
+    }
+
+    Ok(())
+}
+
+fn write_file(path: &Path, content: &str) -> Result<()> {
+    create_parent_dir(path)?;
+    unwrap!(fs::write(path, content), "couldn't write {path}");
     Ok(())
 }
 
-pub fn mode_check(root: &NavFolder) -> Result<()> {
+// `strict_errors` is the number of warnings that are errors in strict mode
+pub fn mode_check(
+    root: &NavFolder,
+    config: &Config,
+    glossary: &Glossary,
+    strict_errors: usize,
+) -> Result<()> {
     debug!(target: "mode", "check");
 
     let mut total = 0;
@@ -26,18 +334,28 @@ pub fn mode_check(root: &NavFolder) -> Result<()> {
 
         // TODO rich diff?
         if page.fixed_content != page.raw_content {
-            error!(target: "", "Fix: {}", page.path);
+            error!(target: "", "Fix: {}", page.rel_path);
             fails += 1;
         } else {
-            debug!(target: "mode_check", "pass {}", page.path);
+            debug!(target: "mode_check", "pass {}", page.rel_path);
         }
     });
 
-    if fails == 0 {
+    let problems = strict_errors
+        + check_requirements(root, &config.file.requirements)
+        + check_glossary(root, glossary)
+        + check_markers(root)
+        + check_code_blocks(root, &config.file.code_blocks)
+        + check_grammar(root, &config.file.grammar)
+        + check_section_refs(root, &number_sections(root));
+
+    if fails != 0 {
+        Err(anyhow!("{fails}/{total} files need fixing :("))
+    } else if problems != 0 {
+        Err(anyhow!("found {problems} problems :("))
+    } else {
         info!(target: "", "All {total} files look good!");
         Ok(())
-    } else {
-        Err(anyhow!("{fails}/{total} files need fixing :("))
     }
 }
 
@@ -51,24 +369,24 @@ pub fn mode_fix(root: &NavFolder) -> Result<()> {
         total += 1;
 
         if page.fixed_content == page.raw_content {
-            debug!(target: "mode_fix", "pass  {}", page.path);
+            debug!(target: "mode_fix", "pass  {}", page.rel_path);
             return Ok(());
         }
 
         fixed += 1;
 
-        let path = &page.path;
+        let rel_path = &page.rel_path;
         let mut file = unwrap!(
-            File::options().write(true).truncate(true).open(path),
-            "couldn't open {path} for fixing",
+            File::options().write(true).truncate(true).open(&page.path),
+            "couldn't open {rel_path} for fixing",
         );
 
         unwrap!(
             file.write_all(page.fixed_content.as_bytes()),
-            "write error while fixing file {path}"
+            "write error while fixing file {rel_path}"
         );
 
-        debug!(target: "mode_fix", "fixed {path}");
+        debug!(target: "mode_fix", "fixed {rel_path}");
         Ok(())
     })?;
 
@@ -80,3 +398,131 @@ pub fn mode_fix(root: &NavFolder) -> Result<()> {
 
     Ok(())
 }
+
+pub fn mode_extract(root: &NavFolder, config: &Config) -> Result<()> {
+    debug!(target: "mode", "extract");
+
+    let src = Path::new(&config.file.build.source).normalize();
+    let out = Path::new(&config.file.extract.output);
+    let manifest = out.join("manifest.json");
+
+    let examples = collect_examples(root)?;
+
+    // only clear the dir if it's from a previous extract, just in case it was misconfigured
+    if out.exists() {
+        ensure!(
+            manifest.exists(),
+            "refusing to clear {out} since it doesn't have a manifest.json"
+        );
+        unwrap!(fs::remove_dir_all(&out), "couldn't clear {out}");
+    }
+
+    for example in &examples {
+        write_file(&out.join(example.file_name()), &example.code)?;
+    }
+
+    let doc_path = |path: &Path| -> Path {
+        path.strip_prefix(&src)
+            .expect("bug? path isn't in the source dir")
+    };
+    write_file(&manifest, &manifest_json(&examples, &doc_path))?;
+
+    info!(target: "", "Extracted {} examples into {out}", examples.len());
+
+    Ok(())
+}
+
+pub fn mode_diff(old: &Path, new: &Path, config: &Config) -> Result<()> {
+    debug!(target: "mode", "diff");
+
+    // each source dir has its own .nrignore
+    let old_source = Source::load(old, &config.file.build)?;
+    let new_source = Source::load(new, &config.file.build)?;
+
+    // the cache isn't saved, since these usually aren't the current source dir
+    let mut cache = Cache::load();
+
+    let old_root = unwrap!(
+        process_root(&mut cache, &old_source, true),
+        "couldn't process {old}"
+    );
+    let new_root = unwrap!(
+        process_root(&mut cache, &new_source, true),
+        "couldn't process {new}"
+    );
+
+    // stdout, so it can be redirected to a file
+    print!("{}", changelog(&old_root, old, &new_root, new));
+
+    Ok(())
+}
+
+pub fn mode_nav(root: &NavFolder, source: &Source, format: NavFormat) -> Result<()> {
+    debug!(target: "mode", "nav");
+
+    // stdout, so it can be piped to other tools
+    match format {
+        NavFormat::Tree => print!("{}", nav_tree(root, source)),
+        NavFormat::Json => print!("{}", nav_json(root, source)),
+    }
+
+    Ok(())
+}
+
+pub fn mode_init(config_path: &Path) -> Result<()> {
+    debug!(target: "mode", "init");
+
+    // the spec is usually named after its repo
+    let cwd = unwrap!(std::env::current_dir(), "couldn't get the current dir");
+    let name = cwd
+        .file_name()
+        .map_or("Specification".into(), |n| n.to_string_lossy());
+
+    for path in init(config_path, &name)? {
+        info!(target: "", "Created {path}");
+    }
+
+    Ok(())
+}
+
+pub fn mode_new(root: &NavFolder, source: &Source, page: &str) -> Result<()> {
+    debug!(target: "mode", "new");
+
+    let (path, index) = new_page(root, source, page)?;
+
+    let path = source.show(&path);
+    match index {
+        Some(index) => info!(
+            target: "",
+            "Created {path} and added it to the nav in {}",
+            source.show(&index)
+        ),
+        None => info!(target: "", "Created {path}"),
+    }
+
+    Ok(())
+}
+
+pub fn mode_move(
+    root: &NavFolder,
+    source: &Source,
+    old: &str,
+    new: &str,
+    alias: bool,
+) -> Result<()> {
+    debug!(target: "mode", "mv");
+
+    let moved = move_page(root, source, old, new, alias)?;
+
+    for path in &moved.updated {
+        info!(target: "", "Updated {}", source.show(path));
+    }
+    info!(
+        target: "",
+        "Moved {} to {}",
+        source.show(&moved.from),
+        source.show(&moved.to)
+    );
+
+    Ok(())
+}
@@ -0,0 +1,247 @@
+use crate::front_matter::{append_list_line, map_nav_lines, NavElem};
+use crate::md;
+use crate::nav::{NavFolder, NavPage};
+use crate::path::Path;
+use crate::scaffold::includes;
+use crate::source::Source;
+use anyhow::{bail, ensure, Result};
+use log::debug;
+use std::collections::BTreeMap;
+use std::fs;
+
+// moves a page, and updates everything that points to it:
+// - the nav entry in its index
+// - links from other pages
+// - relative links in the page itself (if it changes dirs)
+//
+// links in included files aren't changed (they're not pages), so check_links reports any that break
+
+/// what `move_page` changed
+pub struct Moved {
+    pub from: Path,
+    pub to: Path,
+    // pages (other than the moved one) that were rewritten
+    pub updated: Vec<Path>,
+}
+
+/// moves `old` to `new` (both relative to the source dir)
+///
+/// if `alias` is true, the old path is added to the page's aliases (so it gets a redirect)
+pub fn move_page(
+    root: &NavFolder,
+    source: &Source,
+    old: &str,
+    new: &str,
+    alias: bool,
+) -> Result<Moved> {
+    let from = source.resolve(&source.dir, old)?;
+    let to = source.resolve(&source.dir, new)?;
+    let (rel_from, rel_to) = (source.show(&from), source.show(&to));
+
+    ensure!(
+        to.extension() == Some("md".as_ref()),
+        "{rel_to} isn't an .md file"
+    );
+    ensure!(!to.exists(), "{rel_to} already exists");
+
+    let mut page = None;
+    root.for_each_page(&mut |p| {
+        if p.path == from {
+            page = Some(p);
+        }
+    });
+    let Some(page) = page else {
+        bail!("{rel_from} isn't a page in the nav");
+    };
+    ensure!(
+        page.fm.nav.is_empty(),
+        "{rel_from} is an index page, move its folder instead"
+    );
+
+    // new content for every page that changes (by its current path)
+    let mut changed = BTreeMap::new();
+    let mut in_nav = false;
+
+    root.try_for_each_page(&mut |p| {
+        if let Some(content) = rewrite_page(p, &from, &to, source, alias, &mut in_nav)? {
+            changed.insert(p.path.clone(), content);
+        }
+        Ok(())
+    })?;
+
+    // pages from `Name: dir/*` aren't listed, so the new dir has to be included too
+    if !in_nav && !is_included(root, &to) {
+        bail!(
+            "{rel_from} comes from an include/*, but nothing includes {}, move it to an included dir instead",
+            source.show(&to.parent().unwrap())
+        );
+    }
+
+    // everything was checked, so now it's safe to touch the file system
+
+    if let Some(dir) = to.parent() {
+        unwrap!(
+            fs::create_dir_all(&dir),
+            "couldn't create dir {}",
+            source.show(&dir)
+        );
+    }
+    unwrap!(
+        fs::rename(&from, &to),
+        "couldn't move {rel_from} to {rel_to}"
+    );
+
+    let mut updated = Vec::new();
+
+    for (path, content) in changed {
+        if path == from {
+            unwrap!(fs::write(&to, content), "couldn't write {rel_to}");
+        } else {
+            unwrap!(
+                fs::write(&path, content),
+                "couldn't write {}",
+                source.show(&path)
+            );
+            updated.push(path);
+        }
+    }
+
+    Ok(Moved { from, to, updated })
+}
+
+// the new content of `page`, if it changes
+//
+// sets `in_nav` if `page` is an index that lists the moved page
+fn rewrite_page(
+    page: &NavPage,
+    from: &Path,
+    to: &Path,
+    source: &Source,
+    alias: bool,
+    in_nav: &mut bool,
+) -> Result<Option<String>> {
+    let path = &page.path;
+    let dir = path.parent().unwrap();
+    let mut fm = page.fm.clone();
+    let is_moved = path == from;
+
+    let nav_changed = update_nav(&mut fm.nav, &dir, from, to);
+    if nav_changed {
+        debug!(target: "mv", "updated the nav in {}", page.rel_path);
+        *in_nav = true;
+    }
+
+    let mut new_alias = None;
+    if is_moved && alias {
+        let old = source
+            .relative(from)
+            .expect("bug? page isn't in the source dir")
+            .to_string();
+        if !fm.aliases.contains(&old) {
+            fm.aliases.push(old.clone());
+            new_alias = Some(unwrap!(
+                serde_yaml::to_string(&old),
+                "couldn't serialize {old:?}"
+            ));
+        }
+    }
+
+    let (_, content) = unwrap!(
+        md::take_front_matter(&page.raw_content),
+        "{}",
+        page.rel_path
+    );
+
+    let content = if is_moved {
+        // relative links have to be relative to the new dir
+        let new_dir = to.parent().unwrap();
+        md::fix_links(content, |dest| {
+            let (target, rest) = md::split_link(dest)?;
+            let joined = if points_to(&dir, target, from) {
+                to.clone()
+            } else {
+                dir.join(target).normalize()
+            };
+
+            // normalizing drops the slash from dir urls
+            let slash = if target.ends_with('/') { "/" } else { "" };
+            Some(format!("{}{slash}{rest}", joined.relative_from(&new_dir)))
+        })
+    } else if links_to(page, from) {
+        md::fix_links(content, |dest| {
+            let (target, fragment) = md::split_link(dest)?;
+            points_to(&dir, target, from).then(|| format!("{}{fragment}", to.relative_from(&dir)))
+        })
+    } else if nav_changed {
+        // only the fm changed
+        content.strip_prefix('\n').unwrap_or(content).to_string()
+    } else {
+        return Ok(None);
+    };
+
+    // only the changed lines of the fm are rewritten
+    let content = md::edit_front_matter(&page.raw_content, &fm, &content, |yaml| {
+        let yaml = map_nav_lines(yaml, |elem| moved_elem(elem, &dir, from, to));
+
+        match &new_alias {
+            Some(alias) => append_list_line(&yaml, "aliases", alias.trim_end()),
+            None => yaml,
+        }
+    });
+
+    Ok(Some(content))
+}
+
+fn links_to(page: &NavPage, target: &Path) -> bool {
+    let dir = page.path.parent().unwrap();
+
+    page.links
+        .iter()
+        .any(|dest| md::split_link(dest).is_some_and(|(path, _)| points_to(&dir, path, target)))
+}
+
+// whether a link's path (from a page in `dir`) points to `target`
+fn points_to(dir: &Path, path: &str, target: &Path) -> bool {
+    dir.join(&*md::decode_path(path)).normalize() == *target
+}
+
+// replaces the file entry for `from` (in a nav in `dir`), and returns whether there was one
+fn update_nav(nav: &mut [NavElem], dir: &Path, from: &Path, to: &Path) -> bool {
+    let mut found = false;
+
+    for elem in nav {
+        if let Some(moved) = moved_elem(elem, dir, from, to) {
+            *elem = moved;
+            found = true;
+        } else if let NavElem::Category { elems, .. } = elem {
+            found |= update_nav(elems, dir, from, to);
+        }
+    }
+
+    found
+}
+
+// the new entry, if `elem` (in a nav in `dir`) is the file entry for `from`
+fn moved_elem(elem: &NavElem, dir: &Path, from: &Path, to: &Path) -> Option<NavElem> {
+    match elem {
+        NavElem::File { name, path } if dir.join(path).normalize() == *from => {
+            Some(NavElem::File {
+                name: name.clone(),
+                path: to.relative_from(dir).to_string(),
+            })
+        }
+        _ => None,
+    }
+}
+
+// whether any nav has `Name: dir/*` for the dir `page` is in
+fn is_included(root: &NavFolder, page: &Path) -> bool {
+    let dir = page.parent().unwrap();
+    let mut found = false;
+
+    root.for_each_page(&mut |p| {
+        found |= includes(&p.fm.nav, &p.path.parent().unwrap(), &dir);
+    });
+
+    found
+}
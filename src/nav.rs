@@ -1,4 +1,5 @@
 use crate::front_matter::FrontMatter;
+use crate::md::{Document, Outline};
 use crate::path::Path;
 use anyhow::Result;
 
@@ -6,13 +7,28 @@ use anyhow::Result;
 #[derive(Debug)]
 pub struct NavPage {
     pub path: Path,
+    // relative to the source dir, for diagnostics
+    pub rel_path: Path,
     pub name: String,
 
     pub fm: FrontMatter,
 
     pub raw_content: String,
-    pub built_content: String,
     pub fixed_content: String,
+    // the built content (with includes expanded), which build mode changes before writing it
+    pub doc: Document,
+
+    // files included by this page (normalized)
+    pub includes: Vec<Path>,
+
+    // destinations of the links in this file (not in includes, since those are relative to the included file)
+    pub links: Vec<String>,
+    // sources of the images in this file (same as links)
+    pub images: Vec<String>,
+    // (included file, dest) of the links and images in included content (the file is normalized)
+    pub included_links: Vec<(Path, String)>,
+    // of the built content (before build mode changes it)
+    pub outline: Outline,
 }
 
 /// A folder with an index
@@ -33,6 +49,9 @@ pub struct NavCategory {
     pub name: String,
 
     pub children: Vec<NavItem>,
+
+    // from `Name: folder/*`, so the children are sorted by name
+    pub include: bool,
 }
 
 #[derive(Debug)]
@@ -43,6 +62,14 @@ pub enum NavItem {
 }
 
 impl NavItem {
+    pub fn name(&self) -> &str {
+        match self {
+            NavItem::Page(x) => &x.name,
+            NavItem::Folder(x) => &x.index.name,
+            NavItem::Category(x) => &x.name,
+        }
+    }
+
     #[allow(clippy::needless_lifetimes)] // ???
     pub fn for_each_page<'s>(&'s self, f: &mut impl FnMut(&'s NavPage)) {
         match self {
@@ -59,6 +86,29 @@ impl NavItem {
             NavItem::Category(x) => x.children.iter().try_for_each(|i| i.try_for_each_page(f)),
         }
     }
+
+    #[allow(clippy::needless_lifetimes)] // ???
+    pub fn for_each_page_mut<'s>(&'s mut self, f: &mut impl FnMut(&'s mut NavPage)) {
+        match self {
+            NavItem::Page(x) => f(x),
+            NavItem::Folder(x) => x.for_each_page_mut(f),
+            NavItem::Category(x) => x.children.iter_mut().for_each(|i| i.for_each_page_mut(f)),
+        }
+    }
+
+    pub fn try_for_each_page_mut(
+        &mut self,
+        f: &mut impl FnMut(&mut NavPage) -> Result<()>,
+    ) -> Result<()> {
+        match self {
+            NavItem::Page(x) => f(x),
+            NavItem::Folder(x) => x.try_for_each_page_mut(f),
+            NavItem::Category(x) => x
+                .children
+                .iter_mut()
+                .try_for_each(|i| i.try_for_each_page_mut(f)),
+        }
+    }
 }
 
 impl NavFolder {
@@ -74,4 +124,22 @@ impl NavFolder {
             .iter()
             .try_for_each(|i| i.try_for_each_page(f))
     }
+
+    #[allow(clippy::needless_lifetimes)] // ???
+    pub fn for_each_page_mut<'s>(&'s mut self, f: &mut impl FnMut(&'s mut NavPage)) {
+        f(&mut self.index);
+        self.children
+            .iter_mut()
+            .for_each(|i| i.for_each_page_mut(f));
+    }
+
+    pub fn try_for_each_page_mut(
+        &mut self,
+        f: &mut impl FnMut(&mut NavPage) -> Result<()>,
+    ) -> Result<()> {
+        f(&mut self.index)?;
+        self.children
+            .iter_mut()
+            .try_for_each(|i| i.try_for_each_page_mut(f))
+    }
 }
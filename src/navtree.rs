@@ -0,0 +1,133 @@
+use crate::nav::{NavFolder, NavItem, NavPage};
+use crate::path::Path;
+use crate::source::Source;
+use serde::Serialize;
+use std::fmt::Write;
+
+// the resolved nav, for checking where pages end up (or for other tools)
+//
+// paths are relative to the source dir
+
+#[derive(Serialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+enum Item {
+    Page {
+        name: String,
+        path: String,
+        // nr-include files
+        includes: Vec<String>,
+    },
+    Folder {
+        name: String,
+        path: String,
+        includes: Vec<String>,
+        children: Vec<Item>,
+    },
+    Category {
+        name: String,
+        // from `folder/*`
+        include: bool,
+        children: Vec<Item>,
+    },
+}
+
+// included files can be outside the source dir
+fn relative(source: &Source, path: &Path) -> String {
+    source
+        .relative(path)
+        .unwrap_or_else(|| path.clone())
+        .to_string()
+}
+
+// a file can be included more than once
+fn includes(page: &NavPage, source: &Source) -> Vec<String> {
+    let mut includes = Vec::new();
+
+    for path in &page.includes {
+        let path = relative(source, path);
+        if !includes.contains(&path) {
+            includes.push(path);
+        }
+    }
+
+    includes
+}
+
+fn folder_item(folder: &NavFolder, source: &Source) -> Item {
+    Item::Folder {
+        name: folder.index.name.clone(),
+        path: relative(source, &folder.index.path),
+        includes: includes(&folder.index, source),
+        children: items(&folder.children, source),
+    }
+}
+
+fn items(children: &[NavItem], source: &Source) -> Vec<Item> {
+    children
+        .iter()
+        .map(|item| match item {
+            NavItem::Page(page) => Item::Page {
+                name: page.name.clone(),
+                path: relative(source, &page.path),
+                includes: includes(page, source),
+            },
+            NavItem::Folder(folder) => folder_item(folder, source),
+            NavItem::Category(category) => Item::Category {
+                name: category.name.clone(),
+                include: category.include,
+                children: items(&category.children, source),
+            },
+        })
+        .collect()
+}
+
+pub fn nav_json(root: &NavFolder, source: &Source) -> String {
+    serde_json::to_string_pretty(&folder_item(root, source)).unwrap() + "\n"
+}
+
+/// one item per line, indented by depth, eg. `Basics (page, guide/basics.md)`
+pub fn nav_tree(root: &NavFolder, source: &Source) -> String {
+    let mut out = String::new();
+    write_tree(&mut out, &folder_item(root, source), 0);
+    out
+}
+
+fn write_tree(out: &mut String, item: &Item, depth: usize) {
+    let indent = "  ".repeat(depth);
+
+    let (name, details, children) = match item {
+        Item::Page {
+            name,
+            path,
+            includes,
+        } => (name, describe("page", path, includes), &[][..]),
+        Item::Folder {
+            name,
+            path,
+            includes,
+            children,
+        } => (name, describe("folder", path, includes), &children[..]),
+        Item::Category {
+            name,
+            include,
+            children,
+        } => {
+            let kind = if *include { "include" } else { "category" };
+            (name, kind.to_string(), &children[..])
+        }
+    };
+
+    writeln!(out, "{indent}{name} ({details})").unwrap();
+
+    for child in children {
+        write_tree(out, child, depth + 1);
+    }
+}
+
+fn describe(kind: &str, path: &str, includes: &[String]) -> String {
+    if includes.is_empty() {
+        format!("{kind}, {path}")
+    } else {
+        format!("{kind}, {path}, includes {}", includes.join(", "))
+    }
+}
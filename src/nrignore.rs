@@ -0,0 +1,176 @@
+use crate::path::Path;
+use anyhow::{Context, Result};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::Match;
+use log::debug;
+use std::cmp::Reverse;
+use std::collections::HashSet;
+use std::fs;
+
+// files that aren't part of the spec (drafts, editor files, etc.)
+//
+// the patterns come from `build.ignore` (relative to the source dir) and `.nrignore` files,
+// all in .gitignore syntax, and ignored files are skipped by dir_check and include/*
+//
+// like .gitignore, a .nrignore applies to its own dir, and takes precedence over the ones above it
+// (the source dir's patterns come from both `<source>/.nrignore` and `build.ignore`)
+//
+// pages listed explicitly in a nav are still used, since that's clearly intended
+
+pub const IGNORE_FILE: &str = ".nrignore";
+
+#[derive(Debug)]
+pub struct Ignore {
+    // normalized source dir
+    root: Path,
+    // (normalized dir, its patterns) for the source dir and every dir with a .nrignore, deepest first
+    matchers: Vec<(Path, Gitignore)>,
+}
+
+impl Ignore {
+    pub fn load(src: &Path, patterns: &[String]) -> Result<Ignore> {
+        let root = src.normalize();
+        let mut builder = GitignoreBuilder::new(&root);
+
+        // ignore files themselves are never published
+        builder.add_line(None, IGNORE_FILE)?;
+
+        add_file(&mut builder, &root)?;
+
+        for pattern in patterns {
+            unwrap!(
+                builder.add_line(None, pattern),
+                "invalid ignore pattern {pattern:?}"
+            );
+        }
+
+        let mut ignore = Ignore {
+            matchers: vec![(root.clone(), builder.build()?)],
+            root,
+        };
+
+        let root = ignore.root.clone();
+        ignore.load_nested(&root, &mut HashSet::new())?;
+
+        let count = ignore.matchers.iter().map(|(_, m)| m.num_ignores());
+        debug!(target: "ignore", "{} ignore patterns", count.sum::<u64>());
+
+        Ok(ignore)
+    }
+
+    // finds the .nrignore files below `dir` (skipping ignored dirs, since nothing in them is used anyway)
+    //
+    // `visited` has the canonical path of every dir so far, since symlinks are followed
+    fn load_nested(&mut self, dir: &Path, visited: &mut HashSet<Path>) -> Result<()> {
+        let canonical = unwrap!(fs::canonicalize(dir), "couldn't resolve dir {dir}");
+        if !visited.insert(Path::new_owned(canonical)) {
+            return Ok(());
+        }
+
+        for entry in unwrap!(fs::read_dir(dir), "couldn't read dir {dir}") {
+            let entry = unwrap!(entry, "couldn't read dir {dir}");
+            let path = Path::new_owned(entry.path()).normalize();
+
+            if !path.is_dir() || self.is_ignored(&path, true) {
+                continue;
+            }
+
+            let mut builder = GitignoreBuilder::new(&path);
+            if add_file(&mut builder, &path)? {
+                self.matchers.push((path.clone(), builder.build()?));
+                // (an ancestor is always shorter)
+                self.matchers
+                    .sort_by_key(|(dir, _)| Reverse(dir.to_string().len()));
+            }
+
+            self.load_nested(&path, visited)?;
+        }
+
+        Ok(())
+    }
+
+    /// whether `path` (or a dir it's in) is ignored
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        let path = path.normalize();
+
+        // anything outside the source dir isn't covered by the patterns
+        let Some(relative) = path.strip_prefix(&self.root) else {
+            return false;
+        };
+        if relative.file_name().is_none() {
+            return false;
+        }
+
+        // the deepest .nrignore with a matching pattern decides
+        for (dir, matcher) in &self.matchers {
+            // a .nrignore doesn't apply to its own dir
+            let below = path.strip_prefix(dir).filter(|p| p.file_name().is_some());
+            if below.is_none() {
+                continue;
+            }
+
+            match matcher.matched_path_or_any_parents(&path, is_dir) {
+                Match::None => {}
+                Match::Ignore(_) => return true,
+                Match::Whitelist(_) => return false,
+            }
+        }
+
+        false
+    }
+}
+
+// adds `dir`'s .nrignore to `builder`, returns whether there was one
+fn add_file(builder: &mut GitignoreBuilder, dir: &Path) -> Result<bool> {
+    let file = dir.join(IGNORE_FILE);
+
+    if !file.exists() {
+        return Ok(false);
+    }
+    if let Some(e) = builder.add(&file) {
+        return Err(e).with_context(|| format!("invalid {file}"));
+    }
+
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nested_ignore_files() {
+        let src =
+            Path::new_owned(std::env::temp_dir()).join(format!("nrignore-{}", std::process::id()));
+        let write = |path: &str, content: &str| {
+            let path = src.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        };
+
+        write(IGNORE_FILE, "*.draft.md\n/drafts/\n");
+        write("guide/.nrignore", "!keep.draft.md\nlocal.md\n");
+        write("guide/sub/a.md", "");
+        // ignored dirs aren't searched
+        write("drafts/.nrignore", "!*.md\n");
+
+        let ignore = Ignore::load(&src, &["*.tmp".into()]).unwrap();
+        let ignored = |path: &str| ignore.is_ignored(&src.join(path), false);
+
+        assert!(ignored("a.draft.md"));
+        assert!(ignored("a.tmp"));
+        assert!(!ignored("local.md"));
+
+        assert!(!ignored("guide/keep.draft.md"));
+        assert!(ignored("guide/other.draft.md"));
+        assert!(ignored("guide/local.md"));
+        assert!(ignored("guide/sub/local.md"));
+        assert!(ignored("guide/.nrignore"));
+
+        assert!(ignored("drafts/a.md"));
+        assert!(ignore.is_ignored(&src.join("drafts"), true));
+        assert!(!ignore.is_ignored(&src.join("guide"), true));
+
+        fs::remove_dir_all(&src).unwrap();
+    }
+}
@@ -0,0 +1,274 @@
+use crate::nav::{NavFolder, NavItem, NavPage};
+use crate::path::Path;
+use anyhow::{bail, Result};
+use log::error;
+use pulldown_cmark::{CowStr, Event, HeadingLevel, Tag};
+use std::collections::HashMap;
+use std::fmt::{self, Display, Formatter};
+
+/// a hierarchical section number, eg. `4.2.1`
+///
+/// the root index is the only thing with an empty number
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct SectionNumber(Vec<u32>);
+
+impl SectionNumber {
+    fn child(&self, n: u32) -> SectionNumber {
+        let mut num = self.clone();
+        num.0.push(n);
+        num
+    }
+
+    fn last(&self) -> u32 {
+        self.0.last().copied().unwrap_or(0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl Display for SectionNumber {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for (i, n) in self.0.iter().enumerate() {
+            if i != 0 {
+                f.write_str(".")?;
+            }
+            Display::fmt(n, f)?;
+        }
+        Ok(())
+    }
+}
+
+/// section numbers of every page and heading in the nav
+///
+/// paths are normalized
+#[derive(Debug, Default)]
+pub struct Numbers {
+    pages: HashMap<Path, SectionNumber>,
+    sections: HashMap<(Path, String), SectionNumber>,
+}
+
+impl Numbers {
+    pub fn page(&self, path: &Path) -> Option<&SectionNumber> {
+        self.pages.get(path)
+    }
+
+    pub fn section(&self, path: &Path, id: &str) -> Option<&SectionNumber> {
+        self.sections.get(&(path.clone(), id.to_string()))
+    }
+
+    fn number_folder(&mut self, folder: &NavFolder, number: SectionNumber) {
+        // the index's own sections come first (they're read first), then the folder's children
+        let sections = self.number_page(&folder.index, number.clone());
+        self.number_children(&folder.children, &number, sections + 1);
+    }
+
+    // numbers `children` under `parent` starting at `n`, and returns the next number
+    fn number_children(&mut self, children: &[NavItem], parent: &SectionNumber, mut n: u32) -> u32 {
+        for item in children {
+            match item {
+                NavItem::Page(page) => {
+                    self.number_page(page, parent.child(n));
+                    n += 1;
+                }
+                NavItem::Folder(folder) => {
+                    self.number_folder(folder, parent.child(n));
+                    n += 1;
+                }
+                // a category has no page to show its number on, so it doesn't get one
+                NavItem::Category(category) => {
+                    n = self.number_children(&category.children, parent, n)
+                }
+            }
+        }
+
+        n
+    }
+
+    // returns the number of top level sections in the page
+    fn number_page(&mut self, page: &NavPage, number: SectionNumber) -> u32 {
+        let path = page.path.normalize();
+
+        // (level, number) of the headings that contain the current one
+        let mut stack: Vec<(HeadingLevel, SectionNumber)> = Vec::new();
+        let mut top_level = 0;
+
+        for h in &page.outline.headings {
+            if h.level == HeadingLevel::H1 {
+                self.sections
+                    .insert((path.clone(), h.id.clone()), number.clone());
+                continue;
+            }
+
+            // skipped levels (h2 -> h4) still only nest one deep
+            let mut n = 1;
+            while let Some((level, num)) = stack.last() {
+                if *level < h.level {
+                    break;
+                }
+                n = num.last() + 1;
+                stack.pop();
+            }
+
+            if stack.is_empty() {
+                top_level = n;
+            }
+            let num = stack.last().map_or(&number, |(_, num)| num).child(n);
+
+            self.sections
+                .insert((path.clone(), h.id.clone()), num.clone());
+            stack.push((h.level, num));
+        }
+
+        self.pages.insert(path, number);
+        top_level
+    }
+
+    /// the number of a `[§](dest)` link target, from a link in `page`
+    ///
+    /// None if it isn't numbered (or is the root index, which has an empty number)
+    pub fn resolve(&self, page: &Path, dest: &str) -> Option<&SectionNumber> {
+        let (file, id) = dest.split_once('#').unwrap_or((dest, ""));
+
+        let target = if file.is_empty() {
+            page.clone()
+        } else {
+            page.parent().unwrap().join(file).normalize()
+        };
+
+        let num = if id.is_empty() {
+            self.page(&target)
+        } else {
+            self.section(&target, id)
+        };
+
+        num.filter(|num| !num.is_empty())
+    }
+}
+
+// numbers are assigned by position in the nav, so they're stable for the same nav tree:
+// - root children are 1, 2, 3, ...
+// - children of a folder are nested under it (4.1, 4.2, ...)
+// - headings are nested under their page (h2 is 4.1, h3 is 4.1.1, ...)
+// - an index page's own headings come first, in reading order, and the folder's children after them
+//   (if folder 4's index has 2 h2s, they're 4.1 and 4.2, and its first child is 4.3),
+//   which also goes for the root index (its h2s are 1, 2, ... and the root children come after)
+// - categories don't have a number (there's no page to show it on),
+//   so their children just continue the numbering of the folder they're in
+pub fn number_sections(root: &NavFolder) -> Numbers {
+    let mut numbers = Numbers::default();
+    numbers.number_folder(root, SectionNumber::default());
+    numbers
+}
+
+// renders `[§](#heading)` (and `[§](page.md#heading)`, `[§](page.md)`) as the target's number,
+// and optionally prefixes every heading with its number
+pub fn apply_numbers(page: &mut NavPage, numbers: &Numbers, prefix: bool) -> Result<()> {
+    let path = page.path.normalize();
+
+    if prefix {
+        // the ids have to be explicit, otherwise the number would end up in the anchor
+        //
+        // they're from the outline (which is what was numbered), since earlier passes change heading text
+        // (eg. `## See [[id]]`), and with it the generated ids
+        let ids = page
+            .outline
+            .headings
+            .iter()
+            .map(|h| h.id.clone())
+            .collect::<Vec<_>>();
+        page.doc.set_heading_ids(&ids)?;
+    }
+
+    let mut unnumbered = Vec::new();
+    let mut unresolved = Vec::new();
+
+    page.doc.transform(|events| {
+        let mut link: Option<CowStr> = None;
+
+        events
+            .into_iter()
+            .flat_map(|e| match e {
+                Event::Start(Tag::Heading(level, Some(id), classes)) if prefix => {
+                    let num = numbers.section(&path, id);
+                    if num.is_none() {
+                        unnumbered.push(id.to_string());
+                    }
+
+                    let start = Event::Start(Tag::Heading(level, Some(id), classes));
+
+                    match num {
+                        Some(num) if !num.is_empty() => {
+                            vec![start, Event::Text(format!("{num} ").into())]
+                        }
+                        _ => vec![start],
+                    }
+                }
+                Event::Start(Tag::Link(ty, dest, title)) => {
+                    link = Some(dest.clone());
+                    vec![Event::Start(Tag::Link(ty, dest, title))]
+                }
+                Event::End(Tag::Link(ty, dest, title)) => {
+                    link = None;
+                    vec![Event::End(Tag::Link(ty, dest, title))]
+                }
+                Event::Text(s) if &*s == "§" && link.is_some() => {
+                    let dest = link.as_deref().unwrap();
+
+                    match numbers.resolve(&path, dest) {
+                        Some(num) => vec![Event::Text(format!("§{num}").into())],
+                        None => {
+                            unresolved.push(dest.to_string());
+                            vec![Event::Text(s)]
+                        }
+                    }
+                }
+                e => vec![e],
+            })
+            .collect()
+    });
+
+    if !unnumbered.is_empty() {
+        bail!("headings without a number: {unnumbered:?}");
+    }
+    if !unresolved.is_empty() {
+        bail!("unresolved section references: {unresolved:?}");
+    }
+
+    Ok(())
+}
+
+// every `[§](dest)` has to point to a numbered page or heading, otherwise the build fails
+//
+// returns the number of problems
+pub fn check_section_refs(root: &NavFolder, numbers: &Numbers) -> usize {
+    let mut problems = 0;
+
+    root.for_each_page(&mut |page| {
+        let path = page.path.normalize();
+        let mut link = None;
+
+        for e in page.doc.events() {
+            match e {
+                Event::Start(Tag::Link(_, dest, _)) => link = Some(dest),
+                Event::End(Tag::Link(..)) => link = None,
+                Event::Text(s) if &*s == "§" => {
+                    if let Some(dest) = link.as_deref() {
+                        if numbers.resolve(&path, dest).is_none() {
+                            error!(
+                                target: "check_section_refs",
+                                "{}: unresolved section reference {dest:?}",
+                                page.rel_path,
+                            );
+                            problems += 1;
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    });
+
+    problems
+}
@@ -2,13 +2,13 @@
 
 use std::ffi::OsStr;
 use std::fmt::{self, Debug, Display, Formatter};
-use std::path::{Path as StdPath, PathBuf};
+use std::path::{Component, Path as StdPath, PathBuf};
 
 /// a file system path.
 /// thats it.
 /// works as you would expect.
 /// implements Display.
-#[derive(Clone, Eq, PartialEq, Hash)]
+#[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
 pub struct Path {
     inner: PathBuf,
 }
@@ -39,6 +39,78 @@ impl Path {
     pub fn parent(&self) -> Option<Path> {
         self.inner.parent().map(Path::new)
     }
+
+    pub fn exists(&self) -> bool {
+        self.inner.exists()
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.inner.is_dir()
+    }
+
+    pub fn strip_prefix(&self, base: &Path) -> Option<Path> {
+        // `.` is a prefix of every relative path
+        let base = base.inner.strip_prefix(".").unwrap_or(&base.inner);
+
+        self.inner.strip_prefix(base).ok().map(Path::new)
+    }
+
+    /// removes `.` and resolves `..` without touching the file system
+    ///
+    /// leading `..`s (that can't be resolved) are kept,
+    /// and a path that resolves to nothing becomes `.` (so it can still be read)
+    pub fn normalize(&self) -> Path {
+        let mut buf = PathBuf::new();
+
+        for c in self.inner.components() {
+            match c {
+                Component::CurDir => {}
+                Component::ParentDir => match buf.components().next_back() {
+                    Some(Component::Normal(_)) => {
+                        buf.pop();
+                    }
+                    // can't go above the root
+                    Some(Component::RootDir | Component::Prefix(_)) => {}
+                    _ => buf.push(".."),
+                },
+                c => buf.push(c),
+            }
+        }
+
+        if buf.as_os_str().is_empty() {
+            buf.push(".");
+        }
+
+        Path::new_owned(buf)
+    }
+
+    /// whether a normalized path stays inside the dir it's relative to
+    /// (not absolute, and no leading `..`)
+    pub fn is_contained(&self) -> bool {
+        self.inner
+            .components()
+            .all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+    }
+
+    /// the relative path that gets from `base` (a directory) to `self`
+    ///
+    /// both paths should be normalized
+    pub fn relative_from(&self, base: &Path) -> Path {
+        let not_cur = |c: &Component| *c != Component::CurDir;
+        let mut ours = self.inner.components().filter(not_cur).peekable();
+        let mut theirs = base.inner.components().filter(not_cur).peekable();
+
+        while ours.peek().is_some() && ours.peek() == theirs.peek() {
+            ours.next();
+            theirs.next();
+        }
+
+        let mut buf = PathBuf::new();
+        theirs.for_each(|_| buf.push(".."));
+        ours.for_each(|c| buf.push(c));
+
+        Path::new_owned(buf)
+    }
 }
 
 impl Debug for Path {
@@ -59,3 +131,48 @@ impl AsRef<StdPath> for Path {
         &self.inner
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn normalize(path: &str) -> String {
+        Path::new(path).normalize().to_string()
+    }
+
+    fn relative(path: &str, base: &str) -> String {
+        Path::new(path).relative_from(&Path::new(base)).to_string()
+    }
+
+    #[test]
+    fn normalize_resolves_dots() {
+        assert_eq!(normalize("src/./guide/../index.md"), "src/index.md");
+        assert_eq!(normalize("src/guide/"), "src/guide");
+        assert_eq!(normalize("a/.."), ".");
+        assert_eq!(normalize("./"), ".");
+    }
+
+    #[test]
+    fn normalize_keeps_leading_parents() {
+        assert_eq!(normalize("../a/../../b"), "../../b");
+        assert_eq!(normalize("/../a"), "/a");
+    }
+
+    #[test]
+    fn relative_from() {
+        assert_eq!(relative("src/guide/a.md", "src/guide"), "a.md");
+        assert_eq!(relative("src/index.md", "src/guide"), "../index.md");
+        assert_eq!(relative("src/b/c.md", "src/a/x"), "../../b/c.md");
+        assert_eq!(relative("src", "src"), "");
+        assert_eq!(relative("guide/a.md", "."), "guide/a.md");
+    }
+
+    #[test]
+    fn strip_prefix_of_current_dir() {
+        let strip = |path: &str, base: &str| Path::new(path).strip_prefix(&Path::new(base));
+
+        assert_eq!(strip("guide/a.md", "."), Some(Path::new("guide/a.md")));
+        assert_eq!(strip("src/a.md", "src"), Some(Path::new("a.md")));
+        assert_eq!(strip("a.md", "src"), None);
+    }
+}
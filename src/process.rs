@@ -1,87 +1,335 @@
+use crate::alias::Aliases;
+use crate::cache::{self, Cache, CachedBuild, CachedPage};
+use crate::dircheck::entry_metadata;
 use crate::front_matter::NavElem;
-use crate::md;
+use crate::links::alias_link;
+use crate::md::{self, Document, Outline};
 use crate::nav::{NavCategory, NavFolder, NavItem, NavPage};
 use crate::path::Path;
+use crate::source::Source;
 use anyhow::{anyhow, bail, ensure, Result};
-use log::{debug, info};
+use log::{debug, info, trace};
+use rayon::prelude::*;
 use std::fs;
 
-pub fn process_item(elem: NavElem, dir: &Path) -> Result<NavItem> {
+pub fn process_item(
+    elem: NavElem,
+    dir: &Path,
+    cache: &mut Cache,
+    source: &Source,
+) -> Result<NavItem> {
     match elem {
         NavElem::File { name, path } => {
             // regular pages can't have nav
-            process_page(&dir.join(path), name)
+            process_page(&source.resolve(dir, &path)?, name, cache, source)
                 .and_then(ensure_page_has_no_nav)
                 .map(NavItem::Page)
         }
         NavElem::Folder { name, path } => {
             // folder/ is implicitly folder/index.md
-            process_folder(&dir.join(path).join("index.md"), name).map(NavItem::Folder)
+            process_folder(
+                &source.resolve(dir, &path)?.join("index.md"),
+                name,
+                cache,
+                source,
+            )
+            .map(NavItem::Folder)
         }
         NavElem::TaggedIndex { name, path } => {
-            process_folder(&dir.join(path), name).map(NavItem::Folder)
+            process_folder(&source.resolve(dir, &path)?, name, cache, source).map(NavItem::Folder)
         }
         NavElem::Include { name, path } => {
-            process_include(&dir.join(path), name).map(NavItem::Category)
+            process_include(&source.resolve(dir, &path)?, name, cache, source)
+                .map(NavItem::Category)
         }
         NavElem::Category { name, elems } => {
-            process_category(dir, name, elems).map(NavItem::Category)
+            process_category(dir, name, elems, cache, source).map(NavItem::Category)
         }
     }
 }
 
-fn process_page(path: &Path, name: Option<String>) -> Result<NavPage> {
-    let raw = unwrap!(fs::read_to_string(path), "could not read file {path}",);
+// processing happens in 2 phases:
+// - resolving the nav, which reads every page and its fm (serially, since index fm decides what's next)
+// - building and fixing every page, which is the expensive part (in parallel)
 
-    let (fm, content) = unwrap!(md::take_front_matter(&raw), "invalid fm in {path}");
+/// processes the whole tree starting at the source dir's index page (both phases)
+pub fn process_root(cache: &mut Cache, source: &Source, docs: bool) -> Result<NavFolder> {
+    let mut root = resolve_root(cache, source)?;
+    build_pages(&mut root, cache, source, docs, None)?;
+    Ok(root)
+}
 
-    // don't debug print empty fm
-    if let Some(fm) = &fm {
-        debug!(target: "take_front_matter", "{path}: {fm:#?}");
-    }
-    let fm = fm.unwrap_or_default();
+/// resolves the nav starting at the source dir's index page (the first phase)
+///
+/// every page's path is normalized, and nav paths can't leave the source dir
+pub fn resolve_root(cache: &mut Cache, source: &Source) -> Result<NavFolder> {
+    process_folder(
+        &source.dir.join("index.md").normalize(),
+        None,
+        cache,
+        source,
+    )
+}
 
-    let built_content = md::build(content);
-    let fixed_content = md::fix(content);
-    let fixed_content = md::prepend_front_matter(&fm, &fixed_content);
+fn process_page(
+    path: &Path,
+    name: Option<String>,
+    cache: &mut Cache,
+    source: &Source,
+) -> Result<NavPage> {
+    let rel_path = source.show(path);
+    let raw = unwrap!(fs::read_to_string(path), "could not read file {rel_path}");
 
-    // enforce all files having a title
-    let title_h1 = unwrap!(
-        md::extract_title_h1(content),
-        "all files must have an h1 title, but couldn't extract one from {path}"
-    );
+    let hash = cache::hash(&raw);
+
+    let fm = match cache.get(path, &hash) {
+        Some(cached) => {
+            trace!(target: "cache", "hit {rel_path}");
+            cached.fm.clone()
+        }
+        None => {
+            let (fm, content) = unwrap!(md::take_front_matter(&raw), "invalid fm in {rel_path}");
+
+            // don't debug print empty fm
+            if let Some(fm) = &fm {
+                debug!(target: "take_front_matter", "{rel_path}: {fm:#?}");
+            }
+            let fm = fm.unwrap_or_default();
+
+            let cached = CachedPage {
+                hash,
+                fm: fm.clone(),
+                content_start: raw.len() - content.len(),
+                build: None,
+            };
+            cache.insert(path, cached);
+
+            fm
+        }
+    };
 
     // if fm specifies a name, use it over an assigned name
     // this is mainly only useful for the root index.md
     let name = if let Some(fm_name) = fm.name.clone() {
         ensure!(
             name.is_none(),
-            "cannot specify both a fm name and a nav name for {path}"
+            "cannot specify both a fm name and a nav name for {rel_path}"
         );
 
         fm_name
     } else {
-        name.unwrap_or(title_h1)
+        // empty until build_pages extracts the title
+        name.unwrap_or_default()
     };
 
     Ok(NavPage {
         path: path.clone(),
+        rel_path,
         name,
         fm,
         raw_content: raw,
-        built_content,
-        fixed_content,
+        // filled in by build_pages
+        fixed_content: String::new(),
+        doc: Document::default(),
+        includes: Vec::new(),
+        links: Vec::new(),
+        images: Vec::new(),
+        included_links: Vec::new(),
+        outline: Outline::default(),
     })
 }
 
-pub fn process_folder(path: &Path, name: Option<String>) -> Result<NavFolder> {
-    let index = process_page(path, name)?;
+/// builds and fixes every page in parallel (the second phase)
+///
+/// pages that were cached aren't built again unless `docs` is true (so their `doc` is empty),
+/// and links to `aliases` (if given) are fixed to point to the canonical page
+///
+/// errors are reported in nav order, so they don't depend on scheduling
+pub fn build_pages(
+    root: &mut NavFolder,
+    cache: &mut Cache,
+    source: &Source,
+    docs: bool,
+    aliases: Option<&Aliases>,
+) -> Result<()> {
+    let mut pages = Vec::new();
+    root.for_each_page_mut(&mut |page| pages.push(page));
+
+    let shared = &*cache;
+    let results = pages
+        .into_par_iter()
+        .map(|page| build_page(page, shared, source, docs, aliases))
+        .collect::<Vec<_>>();
+
+    for res in results {
+        if let Some((path, build)) = res? {
+            cache.set_processed(&path, build);
+        }
+    }
+
+    // names weren't known until now
+    sort_includes(&mut root.children);
+
+    Ok(())
+}
+
+// everything is done with a single parse of the page (see md::Document),
+// and included files are parsed on their own and spliced in
+//
+// a cached build (whose includes haven't changed) is used as is when `docs` isn't needed,
+// unless it links to an alias that has to be fixed (the cached fixed content never fixes those,
+// since it only depends on the page itself)
+//
+// returns the build if it wasn't cached yet
+fn build_page(
+    page: &mut NavPage,
+    cache: &Cache,
+    source: &Source,
+    docs: bool,
+    aliases: Option<&Aliases>,
+) -> Result<Option<(Path, CachedBuild)>> {
+    let (path, rel_path) = (&page.path, &page.rel_path);
+    let dir = path.parent().unwrap().normalize();
+    let cached = cache
+        .peek(path)
+        .expect("bug? page wasn't cached by process_page");
+
+    let alias_links = |links: &[String]| {
+        aliases.filter(|aliases| {
+            links
+                .iter()
+                .any(|dest| alias_link(aliases, &dir, dest).is_some())
+        })
+    };
+
+    let fresh = cached.build.as_ref().filter(|build| build.is_fresh());
+
+    if let Some(build) = fresh.filter(|build| !docs && alias_links(&build.links).is_none()) {
+        trace!(target: "cache", "skipping build of {rel_path}");
+        set_build(page, build);
+        return Ok(None);
+    }
+
+    let doc = md::Document::parse(
+        rel_path,
+        &page.raw_content,
+        cached.content_start..page.raw_content.len(),
+    );
+
+    // these only depend on the page itself
+    let (title_h1, fixed_content) = match &cached.build {
+        Some(build) => (build.title_h1.clone(), build.fixed_content.clone()),
+        None => {
+            // enforce all files having a title
+            let title_h1 = unwrap!(
+                doc.title_h1(),
+                "all files must have an h1 title, but couldn't extract one from {rel_path}"
+            );
+            let fixed_content = md::fix_front_matter(&page.raw_content, &page.fm, &doc.fix());
+            (title_h1, fixed_content)
+        }
+    };
+
+    let links = doc.links();
+    let images = doc.images();
+
+    let page_fixed_content = match alias_links(&links) {
+        Some(aliases) => {
+            let fixed = doc.fix_links(|dest| alias_link(aliases, &dir, dest));
+            Some(md::fix_front_matter(&page.raw_content, &page.fm, &fixed))
+        }
+        None => None,
+    };
+
+    let (doc, includes) = unwrap!(md::build(doc, path, source), "couldn't build {rel_path}");
+
+    let includes = includes
+        .into_iter()
+        .map(|path| {
+            let raw = unwrap!(
+                fs::read_to_string(&path),
+                "couldn't read {}",
+                source.show(&path)
+            );
+            Ok((path.to_string(), cache::hash(&raw)))
+        })
+        .collect::<Result<_>>()?;
+
+    let included_links = doc
+        .included_links()
+        .into_iter()
+        .map(|(file, dest)| (source.dir.join(file).normalize().to_string(), dest))
+        .collect();
+
+    let build = CachedBuild {
+        title_h1,
+        fixed_content,
+        links,
+        images,
+        included_links,
+        outline: doc.outline(),
+        includes,
+    };
+
+    set_build(page, &build);
+    page.doc = doc;
+    if let Some(fixed_content) = page_fixed_content {
+        page.fixed_content = fixed_content;
+    }
+
+    Ok(fresh.is_none().then(|| (page.path.clone(), build)))
+}
+
+fn set_build(page: &mut NavPage, build: &CachedBuild) {
+    page.links = build.links.clone();
+    page.images = build.images.clone();
+    page.included_links = build
+        .included_links
+        .iter()
+        .map(|(file, dest)| (Path::new(file), dest.clone()))
+        .collect();
+    page.outline = build.outline.clone();
+    page.includes = build
+        .includes
+        .iter()
+        .map(|(path, _)| Path::new(path))
+        .collect();
+
+    if page.name.is_empty() {
+        page.name = build.title_h1.clone();
+    }
+    page.fixed_content = build.fixed_content.clone();
+}
+
+fn sort_includes(items: &mut [NavItem]) {
+    for item in items {
+        match item {
+            NavItem::Page(_) => {}
+            NavItem::Folder(folder) => sort_includes(&mut folder.children),
+            NavItem::Category(category) => {
+                if category.include {
+                    category.children.sort_by(|x, y| x.name().cmp(y.name()));
+                }
+                sort_includes(&mut category.children);
+            }
+        }
+    }
+}
+
+pub fn process_folder(
+    path: &Path,
+    name: Option<String>,
+    cache: &mut Cache,
+    source: &Source,
+) -> Result<NavFolder> {
+    let index = process_page(path, name, cache, source)?;
 
     let dir = path.parent().unwrap();
 
     ensure!(
         !index.fm.nav.is_empty(),
-        "index page {path} is missing fm nav"
+        "index page {} is missing fm nav",
+        index.rel_path
     );
 
     let children = index
@@ -89,7 +337,7 @@ pub fn process_folder(path: &Path, name: Option<String>) -> Result<NavFolder> {
         .nav
         .clone()
         .into_iter()
-        .map(|elem| process_item(elem, &dir))
+        .map(|elem| process_item(elem, &dir, cache, source))
         .collect::<Result<Vec<NavItem>>>()?;
 
     Ok(NavFolder { index, children })
@@ -97,56 +345,84 @@ pub fn process_folder(path: &Path, name: Option<String>) -> Result<NavFolder> {
 
 // includes are sorted alphabetical (by name or path?)
 //
-// will ignore directories and non .md files
-pub fn process_include(dir: &Path, name: String) -> Result<NavCategory> {
+// will ignore directories, non .md files, and ignored files (see nrignore.rs)
+// symlinks are handled like in dir_check
+pub fn process_include(
+    dir: &Path,
+    name: String,
+    cache: &mut Cache,
+    source: &Source,
+) -> Result<NavCategory> {
+    let shown = source.show(dir);
     let read_dir = unwrap!(
         fs::read_dir(dir),
-        "couldn't read include/* directory {name} at {dir}"
+        "couldn't read include/* directory {name} at {shown}"
     );
     let mut children = Vec::new();
 
     for entry in read_dir {
-        let entry = entry.unwrap();
+        let entry = unwrap!(entry, "couldn't read include/* directory {name} at {shown}");
         let path = Path::new_owned(entry.path());
-        let metadata = entry.metadata().unwrap();
+        let shown = source.show(&path);
 
+        let Some(metadata) = entry_metadata(&path, &shown, source.symlinks)? else {
+            continue;
+        };
+
+        if source.ignore.is_ignored(&path, metadata.is_dir()) {
+            info!(target: "process_include", "skipping ignored {shown}");
+            continue;
+        }
         if metadata.is_dir() {
-            info!(target: "process_include", "skipping directory {path}");
+            info!(target: "process_include", "skipping directory {shown}");
             continue;
         }
         if path.extension() != Some("md".as_ref()) {
-            info!(target: "process_include", "skipping non .md file {path}");
+            info!(target: "process_include", "skipping non .md file {shown}");
             continue;
         }
         if path.file_name() == Some("index.md".as_ref()) {
-            bail!("process_include: cannot include/* an index file at {path}");
+            bail!("process_include: cannot include/* an index file at {shown}");
         }
 
         // regular pages can't have nav
-        children.push(process_page(&path, None).and_then(ensure_page_has_no_nav)?);
+        children.push(process_page(&path, None, cache, source).and_then(ensure_page_has_no_nav)?);
     }
 
-    children.sort_by(|x, y| x.name.cmp(&y.name));
-
     Ok(NavCategory {
         name,
         children: children.into_iter().map(NavItem::Page).collect(),
+        // sorted by build_pages
+        include: true,
     })
 }
 
-pub fn process_category(dir: &Path, name: String, elems: Vec<NavElem>) -> Result<NavCategory> {
+pub fn process_category(
+    dir: &Path,
+    name: String,
+    elems: Vec<NavElem>,
+    cache: &mut Cache,
+    source: &Source,
+) -> Result<NavCategory> {
     let children = elems
         .into_iter()
-        .map(|elem| process_item(elem, dir))
+        .map(|elem| process_item(elem, dir, cache, source))
         .collect::<Result<Vec<NavItem>>>()?;
 
-    Ok(NavCategory { name, children })
+    Ok(NavCategory {
+        name,
+        children,
+        include: false,
+    })
 }
 
 fn ensure_page_has_no_nav(page: NavPage) -> Result<NavPage> {
     if page.fm.nav.is_empty() {
         Ok(page)
     } else {
-        Err(anyhow!("non index page {} cannot have fm nav", page.path))
+        Err(anyhow!(
+            "non index page {} cannot have fm nav",
+            page.rel_path
+        ))
     }
 }
@@ -0,0 +1,420 @@
+use crate::config::Requirements as RequirementsConfig;
+use crate::md::Section;
+use crate::nav::{NavFolder, NavPage};
+use crate::path::Path;
+use log::error;
+use pulldown_cmark::{Event, Tag};
+use serde::Serialize;
+use std::collections::HashMap;
+
+// rfc 2119 keywords, eg. `The length MUST be 4 bytes.`
+//
+// a requirement is a sentence (in a paragraph or list item) with a keyword in it
+// it can be given a stable id by putting `{#req-id}` at the end of the sentence:
+// `The length MUST be 4 bytes {#req-len}.`
+
+#[derive(Debug)]
+pub struct Requirement {
+    pub id: Option<String>,
+    pub keyword: &'static str,
+    // normalized
+    pub page: Path,
+    pub section: Section,
+    pub text: String,
+}
+
+// a sentence, and the heading it's under
+struct Sentence {
+    section: Section,
+    text: String,
+    id: Option<String>,
+}
+
+pub fn collect_requirements(root: &NavFolder) -> Vec<Requirement> {
+    let mut reqs = Vec::new();
+
+    root.for_each_page(&mut |page| {
+        let path = page.path.normalize();
+
+        for s in sentences(page) {
+            if let Some(keyword) = keyword(&s.text) {
+                reqs.push(Requirement {
+                    id: s.id,
+                    keyword,
+                    page: path.clone(),
+                    section: s.section,
+                    text: s.text,
+                });
+            }
+        }
+    });
+
+    reqs
+}
+
+// reports duplicate ids, missing ids (if they're required),
+// and lowercase "must"/"shall" in sections that have requirements (which probably should be uppercase)
+//
+// returns the number of problems
+pub fn check_requirements(root: &NavFolder, config: &RequirementsConfig) -> usize {
+    let mut problems = 0;
+    let mut ids = HashMap::<String, Path>::new();
+
+    root.for_each_page(&mut |page| {
+        let sentences = sentences(page);
+
+        let normative = sentences
+            .iter()
+            .filter(|s| keyword(&s.text).is_some())
+            .map(|s| s.section.id.as_str())
+            .collect::<Vec<_>>();
+
+        for s in &sentences {
+            if let Some(id) = &s.id {
+                if let Some(other) = ids.insert(id.clone(), page.rel_path.clone()) {
+                    error!(
+                        target: "check_requirements",
+                        "{}: duplicate requirement id {id:?} (already used in {other})",
+                        page.rel_path,
+                    );
+                    problems += 1;
+                }
+            }
+
+            if keyword(&s.text).is_some() {
+                if s.id.is_none() && config.require_ids {
+                    error!(
+                        target: "check_requirements",
+                        "{}: requirement is missing an id: {:?}",
+                        page.rel_path,
+                        s.text,
+                    );
+                    problems += 1;
+                }
+            } else if normative.contains(&s.section.id.as_str()) {
+                if let Some(word) = words(&s.text).find(|w| matches!(*w, "must" | "shall")) {
+                    error!(
+                        target: "check_requirements",
+                        "{}: lowercase {word:?} in normative section {:?}: {:?}",
+                        page.rel_path,
+                        s.section.title,
+                        s.text,
+                    );
+                    problems += 1;
+                }
+            }
+        }
+    });
+
+    problems
+}
+
+// turns `{#req-id}` markers into anchors so the register can link to them
+pub fn apply_requirement_ids(page: &mut NavPage) {
+    let mut in_code = false;
+
+    page.doc.transform(|events| {
+        events
+            .into_iter()
+            .flat_map(|e| match e {
+                Event::Start(Tag::CodeBlock(_)) => {
+                    in_code = true;
+                    vec![e]
+                }
+                Event::End(Tag::CodeBlock(_)) => {
+                    in_code = false;
+                    vec![e]
+                }
+                Event::Text(s) if !in_code && s.contains("{#") => {
+                    let mut events = Vec::new();
+                    let mut rest = &*s;
+
+                    while let Some((before, id, after)) = split_id(rest) {
+                        events.push(Event::Text(before.trim_end().to_string().into()));
+                        events.push(Event::Html(format!("<a id=\"{id}\"></a>").into()));
+                        rest = after;
+                    }
+
+                    events.push(Event::Text(rest.to_string().into()));
+                    events
+                }
+                e => vec![e],
+            })
+            .collect()
+    });
+}
+
+// `before {#id} after` -> (`before `, `id`, ` after`)
+fn split_id(s: &str) -> Option<(&str, &str, &str)> {
+    let start = s.find("{#")?;
+    let len = s[start..].find('}')?;
+    let id = &s[start + 2..start + len];
+
+    let valid = !id.is_empty()
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | ':'));
+
+    valid.then(|| (&s[..start], id, &s[start + len + 1..]))
+}
+
+fn sentences(page: &NavPage) -> Vec<Sentence> {
+    let mut headings = page.outline.headings.iter();
+
+    let mut section = Section {
+        id: String::new(),
+        title: page.name.clone(),
+    };
+    let mut sentences = Vec::<Sentence>::new();
+
+    // text of the current paragraph/list item
+    let mut buf = String::new();
+    let mut in_heading = false;
+    let mut in_code = false;
+
+    let mut flush = |buf: &mut String, section: &Section| {
+        split_sentences(buf, &mut |text| {
+            let mut id = None;
+            let mut clean = String::new();
+            let mut rest = text;
+
+            while let Some((before, marker, after)) = split_id(rest) {
+                clean.push_str(before.trim_end());
+                id = Some(marker.to_string());
+                rest = after;
+            }
+            clean.push_str(rest);
+
+            let clean = clean.trim().to_string();
+
+            // a sentence that's only an id belongs to the one before it
+            // `The length MUST be 4 bytes. {#req-len}`
+            if clean.is_empty() || clean == "." {
+                if let (Some(prev), Some(id)) = (sentences.last_mut(), id) {
+                    prev.id = Some(id);
+                }
+                return;
+            }
+
+            sentences.push(Sentence {
+                section: section.clone(),
+                text: clean,
+                id,
+            });
+        });
+        buf.clear();
+    };
+
+    for e in page.doc.events() {
+        match e {
+            Event::Start(Tag::Heading(..)) => {
+                flush(&mut buf, &section);
+                in_heading = true;
+
+                let h = headings.next().expect("bug? heading count changed");
+                section = Section {
+                    id: h.id.clone(),
+                    title: h.text.clone(),
+                };
+            }
+            Event::End(Tag::Heading(..)) => in_heading = false,
+            Event::Start(Tag::CodeBlock(_)) => in_code = true,
+            Event::End(Tag::CodeBlock(_)) => in_code = false,
+
+            Event::Text(s) | Event::Code(s) if !in_heading && !in_code => buf.push_str(&s),
+            Event::SoftBreak | Event::HardBreak => buf.push(' '),
+
+            // block boundaries
+            Event::Start(
+                Tag::Paragraph | Tag::Item | Tag::List(_) | Tag::BlockQuote | Tag::TableCell,
+            )
+            | Event::End(
+                Tag::Paragraph | Tag::Item | Tag::List(_) | Tag::BlockQuote | Tag::TableCell,
+            ) => flush(&mut buf, &section),
+
+            _ => {}
+        }
+    }
+
+    flush(&mut buf, &section);
+
+    sentences
+}
+
+// sentences end with `.`, `!` or `?` followed by whitespace
+fn split_sentences(text: &str, f: &mut impl FnMut(&str)) {
+    let mut start = 0;
+    let mut chars = text.char_indices().peekable();
+
+    while let Some((i, c)) = chars.next() {
+        let at_end = matches!(chars.peek(), Some((_, c)) if c.is_whitespace());
+
+        if matches!(c, '.' | '!' | '?') && at_end {
+            f(&text[start..=i]);
+            start = i + 1;
+        }
+    }
+
+    if !text[start..].trim().is_empty() {
+        f(&text[start..]);
+    }
+}
+
+fn words(text: &str) -> impl Iterator<Item = &str> {
+    text.split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|w| !w.is_empty())
+}
+
+// the first rfc 2119 keyword in the text
+fn keyword(text: &str) -> Option<&'static str> {
+    let mut words = words(text).peekable();
+
+    while let Some(w) = words.next() {
+        let not = words.peek() == Some(&"NOT");
+
+        let kw = match w {
+            "MUST" if not => "MUST NOT",
+            "SHALL" if not => "SHALL NOT",
+            "SHOULD" if not => "SHOULD NOT",
+            "NOT" if words.peek() == Some(&"RECOMMENDED") => "NOT RECOMMENDED",
+            "MUST" => "MUST",
+            "REQUIRED" => "REQUIRED",
+            "SHALL" => "SHALL",
+            "SHOULD" => "SHOULD",
+            "RECOMMENDED" => "RECOMMENDED",
+            "MAY" => "MAY",
+            "OPTIONAL" => "OPTIONAL",
+            _ => continue,
+        };
+
+        return Some(kw);
+    }
+
+    None
+}
+
+#[derive(Serialize)]
+struct RegisterEntry<'a> {
+    id: Option<&'a str>,
+    keyword: &'static str,
+    page: String,
+    section: &'a str,
+    section_id: &'a str,
+    section_number: Option<String>,
+    text: &'a str,
+}
+
+/// requirements.json
+///
+/// `doc_path` maps a page path to its path in the docs dir,
+/// `number` gives the section number of a (page, heading id)
+pub fn register_json(
+    reqs: &[Requirement],
+    doc_path: &impl Fn(&Path) -> Path,
+    number: &impl Fn(&Path, &str) -> Option<String>,
+) -> String {
+    let entries = reqs
+        .iter()
+        .map(|r| RegisterEntry {
+            id: r.id.as_deref(),
+            keyword: r.keyword,
+            page: doc_path(&r.page).to_string(),
+            section: &r.section.title,
+            section_id: &r.section.id,
+            section_number: number(&r.page, &r.section.id),
+            text: &r.text,
+        })
+        .collect::<Vec<_>>();
+
+    serde_json::to_string_pretty(&entries).unwrap() + "\n"
+}
+
+/// requirements.md, a table of every requirement
+///
+/// `register` is the path of the page itself (to make the links relative)
+pub fn register_md(
+    reqs: &[Requirement],
+    register: &Path,
+    doc_path: &impl Fn(&Path) -> Path,
+    number: &impl Fn(&Path, &str) -> Option<String>,
+) -> String {
+    let dir = register.parent().unwrap();
+    let mut md = String::from("# Requirements\n\n");
+
+    md.push_str("| ID | Keyword | Section | Requirement |\n");
+    md.push_str("| --- | --- | --- | --- |\n");
+
+    for r in reqs {
+        let page = doc_path(&r.page).relative_from(&dir);
+
+        let id = match &r.id {
+            Some(id) => format!("[{id}]({page}#{id})"),
+            None => String::new(),
+        };
+
+        let section = match number(&r.page, &r.section.id) {
+            Some(num) => format!("{num} {}", r.section.title),
+            None => r.section.title.clone(),
+        };
+
+        let anchor = if r.section.id.is_empty() {
+            String::new()
+        } else {
+            format!("#{}", r.section.id)
+        };
+
+        md.push_str(&format!(
+            "| {id} | {} | [{}]({page}{anchor}) | {} |\n",
+            r.keyword,
+            escape_cell(&section),
+            escape_cell(&r.text),
+        ));
+    }
+
+    md
+}
+
+fn escape_cell(s: &str) -> String {
+    s.replace('|', "\\|")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sentences(text: &str) -> Vec<String> {
+        let mut out = Vec::new();
+        split_sentences(text, &mut |s| out.push(s.to_string()));
+        out
+    }
+
+    #[test]
+    fn split_sentences_at_punctuation() {
+        assert_eq!(
+            sentences("It MUST work. Does it? Yes! Trailing"),
+            ["It MUST work.", " Does it?", " Yes!", " Trailing"],
+        );
+    }
+
+    #[test]
+    fn split_sentences_needs_whitespace() {
+        assert_eq!(sentences("Version 1.2 is fine."), ["Version 1.2 is fine."]);
+        assert_eq!(sentences("See a.md.Then b"), ["See a.md.Then b"]);
+        assert_eq!(sentences("Done.  "), ["Done."]);
+    }
+
+    #[test]
+    fn keyword_negations() {
+        assert_eq!(keyword("It MUST NOT fail"), Some("MUST NOT"));
+        assert_eq!(keyword("It SHOULD NOT fail"), Some("SHOULD NOT"));
+        assert_eq!(keyword("It is NOT RECOMMENDED"), Some("NOT RECOMMENDED"));
+        assert_eq!(keyword("It is NOT REQUIRED"), Some("REQUIRED"));
+    }
+
+    #[test]
+    fn keyword_first_and_exact() {
+        assert_eq!(keyword("It MAY, but SHOULD, be done"), Some("MAY"));
+        assert_eq!(keyword("Senders (MUST) ack"), Some("MUST"));
+        assert_eq!(keyword("It must be MUSTARD"), None);
+    }
+}
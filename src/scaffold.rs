@@ -0,0 +1,192 @@
+use crate::front_matter::{append_list_line, NavElem};
+use crate::md;
+use crate::nav::{NavFolder, NavPage};
+use crate::path::Path;
+use crate::source::Source;
+use anyhow::{bail, ensure, Result};
+use log::info;
+use std::fs;
+
+// `init` creates a new spec, and `new` adds a page to an existing one
+//
+// new pages are added to the end of the nav of the closest index above them (that's in the nav),
+// so they're never unused
+
+const INDEX: &str = "---
+nav:
+- introduction.md
+---
+# {name}
+
+<!-- nr-children -->
+
+";
+
+const INTRODUCTION: &str = "# Introduction
+";
+
+/// the config and a root index (with one page, since indexes need a nav)
+pub fn init(config_path: &Path, name: &str) -> Result<Vec<Path>> {
+    let src = Path::new("src");
+
+    let config = format!(
+        "version = {}\n\
+         \n\
+         [metadata]\n\
+         name = {}\n\
+         description = \"\"\n\
+         copyright = \"\"\n\
+         \n\
+         [build]\n\
+         source = \"{src}\"\n\
+         output = \"mkdocs\"\n",
+        toml::Value::from(format!(
+            "{}.{}",
+            env!("CARGO_PKG_VERSION_MAJOR"),
+            env!("CARGO_PKG_VERSION_MINOR")
+        )),
+        toml::Value::from(name),
+    );
+
+    let files = [
+        (config_path.clone(), config),
+        (src.join("index.md"), INDEX.replace("{name}", name)),
+        (src.join("introduction.md"), INTRODUCTION.to_string()),
+    ];
+
+    for (path, _) in &files {
+        ensure!(!path.exists(), "{path} already exists");
+    }
+
+    unwrap!(fs::create_dir_all(&src), "couldn't create dir {src}");
+
+    for (path, content) in &files {
+        unwrap!(fs::write(path, content), "couldn't write {path}");
+    }
+
+    Ok(files.into_iter().map(|(path, _)| path).collect())
+}
+
+// `getting-started.md` -> `Getting Started`
+fn title(path: &Path) -> String {
+    let stem = path.to_string();
+    let stem = stem.rsplit('/').next().unwrap();
+    let stem = stem.strip_suffix(".md").unwrap_or(stem);
+
+    stem.split(['-', '_', ' '])
+        .filter(|w| !w.is_empty())
+        .map(|w| {
+            let mut chars = w.chars();
+            let first = chars.next().unwrap();
+            first.to_uppercase().chain(chars).collect::<String>()
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// creates a page (`page` is relative to the source dir), and adds it to the nav
+///
+/// returns the page, and the index it was added to (None if an include/* already covers it)
+pub fn new_page(root: &NavFolder, source: &Source, page: &str) -> Result<(Path, Option<Path>)> {
+    let path = source.resolve(&source.dir, page)?;
+    let rel_path = source.show(&path);
+
+    ensure!(
+        path.extension() == Some("md".as_ref()),
+        "{rel_path} isn't an .md file"
+    );
+    ensure!(
+        path.file_name() != Some("index.md".as_ref()),
+        "new can't create index pages (they need a nav)"
+    );
+    ensure!(!path.exists(), "{rel_path} already exists");
+
+    let dir = path.parent().unwrap();
+
+    // an index.md on disk that isn't in the nav wouldn't make the page used
+    let Some(index_page) = closest_index(root, &dir) else {
+        bail!("couldn't find an index in the nav for {rel_path}");
+    };
+    let index = index_page.path.clone();
+    let index_dir = index.parent().unwrap();
+
+    let raw = &index_page.raw_content;
+    let (_, content) = unwrap!(md::take_front_matter(raw), "{}", index_page.rel_path);
+    let mut fm = index_page.fm.clone();
+
+    let title = title(&path);
+    create_page(&path, &title)?;
+
+    if includes(&fm.nav, &index_dir, &dir) {
+        info!(
+            target: "new",
+            "{} is already included by {}",
+            source.show(&dir),
+            index_page.rel_path
+        );
+        return Ok((path, None));
+    }
+
+    let elem = NavElem::File {
+        name: None,
+        path: path.relative_from(&index_dir).to_string(),
+    };
+    let line = unwrap!(serde_yaml::to_string(&elem), "couldn't serialize {elem:?}");
+
+    fm.nav.push(elem);
+
+    // take_front_matter leaves the newline after the closing `---`
+    let content = content.strip_prefix('\n').unwrap_or(content);
+    let content = md::edit_front_matter(raw, &fm, content, |yaml| {
+        append_list_line(yaml, "nav", line.trim_end())
+    });
+    unwrap!(
+        fs::write(&index, content),
+        "couldn't write {}",
+        index_page.rel_path
+    );
+
+    Ok((path, Some(index)))
+}
+
+// the index in the nav (a page with a nav) whose dir is the closest one above `dir`
+fn closest_index<'a>(root: &'a NavFolder, dir: &Path) -> Option<&'a NavPage> {
+    let mut closest: Option<(&NavPage, Path)> = None;
+
+    root.for_each_page(&mut |page| {
+        let index_dir = page.path.parent().unwrap();
+
+        if page.fm.nav.is_empty() || dir.strip_prefix(&index_dir).is_none() {
+            return;
+        }
+
+        // (every candidate is above `dir`, so the longest one is the closest)
+        if closest.as_ref().map_or(true, |(_, c)| {
+            index_dir.to_string().len() > c.to_string().len()
+        }) {
+            closest = Some((page, index_dir));
+        }
+    });
+
+    closest.map(|(page, _)| page)
+}
+
+fn create_page(path: &Path, title: &str) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        unwrap!(fs::create_dir_all(&dir), "couldn't create dir {dir}");
+    }
+    unwrap!(
+        fs::write(path, format!("# {title}\n")),
+        "couldn't write {path}"
+    );
+    Ok(())
+}
+
+/// whether `dir` is included (`Name: dir/*`) by a nav in `index_dir`
+pub fn includes(nav: &[NavElem], index_dir: &Path, dir: &Path) -> bool {
+    nav.iter().any(|elem| match elem {
+        NavElem::Include { path, .. } => index_dir.join(path).normalize() == *dir,
+        NavElem::Category { elems, .. } => includes(elems, index_dir, dir),
+        _ => false,
+    })
+}
@@ -0,0 +1,89 @@
+use crate::config::{Build, Symlinks};
+use crate::nrignore::Ignore;
+use crate::path::Path;
+use anyhow::{ensure, Result};
+
+/// a source dir, and the rules for which of its files are part of the spec
+#[derive(Debug)]
+pub struct Source {
+    // normalized
+    pub dir: Path,
+    pub ignore: Ignore,
+    pub symlinks: Symlinks,
+}
+
+impl Source {
+    pub fn load(dir: &Path, config: &Build) -> Result<Source> {
+        let ignore = unwrap!(Ignore::load(dir, &config.ignore), "ignore error");
+
+        Ok(Source {
+            dir: dir.normalize(),
+            ignore,
+            symlinks: config.symlinks,
+        })
+    }
+
+    /// `path` relative to the source dir (eg. `guide/index.md`),
+    /// or None if it's outside of it
+    pub fn relative(&self, path: &Path) -> Option<Path> {
+        path.normalize()
+            .strip_prefix(&self.dir)
+            .filter(Path::is_contained)
+    }
+
+    /// how `path` is shown in diagnostics: relative to the source dir if it's in it
+    pub fn show(&self, path: &Path) -> Path {
+        self.relative(path).unwrap_or_else(|| path.clone())
+    }
+
+    /// joins a nav (or include) path onto `dir` (normalized), making sure it doesn't escape the source dir
+    pub fn resolve(&self, dir: &Path, path: &str) -> Result<Path> {
+        let joined = dir.join(path).normalize();
+
+        ensure!(
+            self.relative(&joined).is_some(),
+            "path {path:?} in {dir} is outside the source dir {}",
+            self.dir
+        );
+
+        Ok(joined)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn source(dir: &str) -> Source {
+        Source {
+            dir: Path::new(dir).normalize(),
+            // (the patterns don't matter here, and loading them walks the whole dir)
+            ignore: Ignore::load(&Path::new("src"), &[]).unwrap(),
+            symlinks: Symlinks::Follow,
+        }
+    }
+
+    #[test]
+    fn current_dir_source() {
+        let source = source(".");
+        assert_eq!(source.dir, Path::new("."));
+
+        let page = source.resolve(&source.dir, "guide/../a.md").unwrap();
+        assert_eq!(page, Path::new("a.md"));
+        assert_eq!(source.relative(&page), Some(Path::new("a.md")));
+
+        assert!(source.resolve(&source.dir, "../a.md").is_err());
+    }
+
+    #[test]
+    fn nested_source() {
+        let source = source("./src/");
+
+        let page = source.resolve(&Path::new("src/guide"), "../a.md").unwrap();
+        assert_eq!(page, Path::new("src/a.md"));
+        assert_eq!(source.relative(&page), Some(Path::new("a.md")));
+
+        assert!(source.resolve(&source.dir, "../a.md").is_err());
+        assert_eq!(source.relative(&Path::new("a.md")), None);
+    }
+}
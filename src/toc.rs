@@ -0,0 +1,160 @@
+use crate::md::{self, Document};
+use crate::nav::{NavFolder, NavItem, NavPage};
+use crate::path::Path;
+use anyhow::Result;
+use log::error;
+use pulldown_cmark::{Event, HeadingLevel};
+
+// `<!-- nr-toc -->` is replaced with a list of links to the page's headings
+// `<!-- nr-children -->` (index pages only) is replaced with a list of links to the folder's children,
+// along with their fm description
+
+// link text can't have unescaped brackets
+fn escape(text: &str) -> String {
+    text.replace('[', "\\[").replace(']', "\\]")
+}
+
+pub fn apply_toc(page: &mut NavPage) -> Result<()> {
+    let headings = page.doc.headings();
+
+    page.doc.replace_directives(|name, _| {
+        if name != "toc" {
+            return Ok(None);
+        }
+
+        let mut toc = String::new();
+        // levels of the headings that contain the current one
+        let mut stack: Vec<HeadingLevel> = Vec::new();
+
+        for h in headings.iter().filter(|h| h.level != HeadingLevel::H1) {
+            while stack.last().is_some_and(|level| *level >= h.level) {
+                stack.pop();
+            }
+
+            let indent = "  ".repeat(stack.len());
+            toc.push_str(&format!("{indent}- [{}](#{})\n", escape(&h.text), h.id));
+
+            stack.push(h.level);
+        }
+
+        Ok(Some(Document::generated(&toc)))
+    })
+}
+
+// this needs to know about the folder, so it goes through the whole tree instead of a single page
+pub fn apply_children(folder: &mut NavFolder) -> Result<()> {
+    let dir = folder.index.path.normalize().parent().unwrap();
+
+    let mut list = String::new();
+    children_list(&folder.children, &dir, 0, &mut list);
+
+    folder.index.doc.replace_directives(|name, _| {
+        Ok((name == "children").then(|| Document::generated(&list)))
+    })?;
+
+    for item in &mut folder.children {
+        apply_children_item(item)?;
+    }
+
+    Ok(())
+}
+
+fn apply_children_item(item: &mut NavItem) -> Result<()> {
+    match item {
+        NavItem::Page(_) => Ok(()),
+        NavItem::Folder(folder) => apply_children(folder),
+        NavItem::Category(category) => category
+            .children
+            .iter_mut()
+            .try_for_each(apply_children_item),
+    }
+}
+
+fn children_list(items: &[NavItem], dir: &Path, depth: usize, out: &mut String) {
+    let indent = "  ".repeat(depth);
+
+    for item in items {
+        match item {
+            NavItem::Page(page) => child_link(page, &page.name, dir, &indent, out),
+            NavItem::Folder(folder) => {
+                child_link(&folder.index, &folder.index.name, dir, &indent, out)
+            }
+            NavItem::Category(category) => {
+                out.push_str(&format!("{indent}- {}\n", category.name));
+                children_list(&category.children, dir, depth + 1, out);
+            }
+        }
+    }
+}
+
+fn child_link(page: &NavPage, name: &str, dir: &Path, indent: &str, out: &mut String) {
+    let path = page.path.normalize().relative_from(dir);
+    out.push_str(&format!("{indent}- [{}]({path})", escape(name)));
+
+    if let Some(description) = &page.fm.description {
+        out.push_str(&format!(" - {description}"));
+    }
+    out.push('\n');
+}
+
+// every directive has to be known and have the right arguments,
+// and nr-children can only be used on index pages
+//
+// returns the number of problems
+pub fn check_markers(root: &NavFolder) -> usize {
+    let mut problems = 0;
+    check_page(&root.index, true, &mut problems);
+    root.children
+        .iter()
+        .for_each(|i| check_item(i, &mut problems));
+    problems
+}
+
+fn check_item(item: &NavItem, problems: &mut usize) {
+    match item {
+        NavItem::Page(page) => check_page(page, false, problems),
+        NavItem::Folder(folder) => {
+            check_page(&folder.index, true, problems);
+            folder.children.iter().for_each(|i| check_item(i, problems));
+        }
+        NavItem::Category(category) => category
+            .children
+            .iter()
+            .for_each(|i| check_item(i, problems)),
+    }
+}
+
+fn check_page(page: &NavPage, is_index: bool, problems: &mut usize) {
+    let mut report = |msg: String| {
+        error!(target: "check_markers", "{}: {msg}", page.rel_path);
+        *problems += 1;
+    };
+
+    for e in page.doc.events() {
+        let Event::Html(html) = e else {
+            continue;
+        };
+        let html = html.trim();
+
+        let Some((name, args)) = md::directive(html) else {
+            if html.starts_with("<!--") && html.contains("nr-") {
+                report(format!("malformed marker {html:?}"));
+            }
+            continue;
+        };
+
+        match name {
+            "toc" | "children" if !args.is_empty() => {
+                report(format!("nr-{name} doesn't take any arguments: {html:?}"));
+            }
+            "children" if !is_index => {
+                report(format!(
+                    "nr-children can only be used on index pages: {html:?}"
+                ));
+            }
+            "toc" | "children" => {}
+            // includes are already expanded (and checked) by now
+            _ => report(format!("unknown marker {html:?}")),
+        }
+    }
+}
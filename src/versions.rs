@@ -0,0 +1,139 @@
+use crate::dircheck::walk_dir_recursive;
+use crate::mkdocs::page_url;
+use crate::path::Path;
+use anyhow::{ensure, Result};
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::fs;
+
+// versioned builds go in `<output>/<spec version>/`, and `<output>/versions.json` lists every version
+// (in the same format as mike, newest first)
+//
+// the newest version is also copied to `<output>/latest/`,
+// and every page of an older version gets a banner linking to it
+
+pub const LATEST: &str = "latest";
+
+const BANNER_MARKER: &str = "<!-- nr-version-banner -->";
+
+// generated files in the docs dir that aren't pages of the spec (like mdbook's SUMMARY.md), so they don't get a banner
+const GENERATED: [&str; 2] = ["SUMMARY.md", "requirements.md"];
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VersionEntry {
+    pub version: String,
+    pub title: String,
+    #[serde(default)]
+    pub aliases: Vec<String>,
+}
+
+pub fn check_version(version: &str) -> Result<()> {
+    ensure!(
+        !version.is_empty() && version != LATEST && version != "." && version != "..",
+        "invalid spec version {version:?}"
+    );
+    ensure!(
+        !version.contains(['/', '\\']),
+        "spec version {version:?} can't contain a path separator"
+    );
+    Ok(())
+}
+
+pub fn read_versions(output: &Path) -> Result<Vec<VersionEntry>> {
+    let path = output.join("versions.json");
+
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+
+    let json = unwrap!(fs::read_to_string(&path), "couldn't read {path}");
+    let versions = unwrap!(serde_json::from_str(&json), "invalid {path}");
+
+    Ok(versions)
+}
+
+pub fn versions_json(versions: &[VersionEntry]) -> String {
+    serde_json::to_string_pretty(versions).unwrap() + "\n"
+}
+
+/// adds (or replaces) `version`, and moves the latest alias to the newest version
+pub fn add_version(versions: &mut Vec<VersionEntry>, version: &str) {
+    versions.retain(|v| v.version != version);
+    versions.push(VersionEntry {
+        version: version.to_string(),
+        title: version.to_string(),
+        aliases: Vec::new(),
+    });
+
+    versions.sort_by(|a, b| compare_versions(&b.version, &a.version));
+
+    for v in versions.iter_mut() {
+        v.aliases.retain(|a| a != LATEST);
+    }
+    versions[0].aliases.push(LATEST.to_string());
+}
+
+// `1.10` is newer than `1.9`, anything that isn't a number is compared as a string
+fn compare_versions(a: &str, b: &str) -> Ordering {
+    let part = |p: &str| (p.parse::<u64>().ok(), p.to_string());
+
+    a.split('.').map(part).cmp(b.split('.').map(part))
+}
+
+fn banner(version: &str, latest: &str, doc: &Path) -> String {
+    // back to the root of the version's site, then over to latest
+    let up = "../".repeat(page_url(doc).matches('/').count() + 1);
+
+    format!(
+        "{BANNER_MARKER}\n\
+         > **Note:** This is version {version} of the specification, which is out of date. \
+         The latest version is [{latest}]({up}{LATEST}/).\n\n"
+    )
+}
+
+fn strip_banner(content: &str) -> &str {
+    match content.strip_prefix(BANNER_MARKER) {
+        Some(rest) => rest.split_once("\n\n").map_or("", |(_, rest)| rest),
+        None => content,
+    }
+}
+
+/// `content` of the page `doc` (relative to the docs dir) with its banner replaced
+pub fn add_banner(content: &str, version: &str, latest: &str, doc: &Path) -> String {
+    banner(version, latest, doc) + strip_banner(content)
+}
+
+/// every page in an old version's docs dir (relative to it), with its banner replaced
+///
+/// the nav of an old version isn't known anymore, so this is every .md file except generated ones
+pub fn banner_pages(docs: &Path, version: &str, latest: &str) -> Result<Vec<(Path, String)>> {
+    if !docs.exists() {
+        warn!(target: "versions", "{docs} doesn't exist, skipping banners for version {version}");
+        return Ok(Vec::new());
+    }
+
+    let mut pages = Vec::new();
+    walk_dir_recursive(docs, &mut |path| {
+        let generated = GENERATED.iter().any(|name| path == docs.join(name));
+
+        if path.extension() == Some("md".as_ref()) && !generated {
+            pages.push(path);
+        }
+    })?;
+
+    let mut out = Vec::new();
+
+    for path in pages {
+        let doc = path
+            .strip_prefix(docs)
+            .expect("bug? path isn't in the docs dir");
+
+        let content = unwrap!(fs::read_to_string(&path), "couldn't read {path}");
+        out.push((doc.clone(), add_banner(&content, version, latest, &doc)));
+    }
+
+    debug!(target: "versions", "added banners to {docs}");
+
+    Ok(out)
+}
@@ -0,0 +1,101 @@
+use crate::md;
+use crate::nav::{NavFolder, NavPage};
+use crate::path::Path;
+use log::error;
+use pulldown_cmark::{Event, LinkType, Tag};
+use std::collections::HashMap;
+
+/// a heading with an explicit id (`# Title {#id}`), which can be referenced from any page with `[[id]]`
+#[derive(Debug)]
+pub struct XRefTarget {
+    // normalized
+    pub page: Path,
+    // relative to the source dir, for diagnostics
+    rel_page: Path,
+    pub title: String,
+}
+
+#[derive(Debug, Default)]
+pub struct XRefs {
+    targets: HashMap<String, XRefTarget>,
+}
+
+impl XRefs {
+    pub fn get(&self, id: &str) -> Option<&XRefTarget> {
+        self.targets.get(id)
+    }
+}
+
+// explicit ids have to be unique across the whole nav, and every `[[id]]` has to point to one
+//
+// returns the targets, and how many duplicate ids and unknown references were found
+pub fn check_xrefs(root: &NavFolder) -> (XRefs, usize) {
+    let mut targets = HashMap::<String, XRefTarget>::new();
+    let mut errors = 0;
+
+    root.for_each_page(&mut |page| {
+        let path = page.path.normalize();
+
+        for h in &page.outline.headings {
+            if !h.explicit_id {
+                continue;
+            }
+
+            if let Some(other) = targets.get(&h.id) {
+                error!(
+                    target: "check_xrefs",
+                    "{}: duplicate id {:?} (already used in {})",
+                    page.rel_path,
+                    h.id,
+                    other.rel_page,
+                );
+                errors += 1;
+                continue;
+            }
+
+            let target = XRefTarget {
+                page: path.clone(),
+                rel_page: page.rel_path.clone(),
+                title: h.text.clone(),
+            };
+            targets.insert(h.id.clone(), target);
+        }
+    });
+
+    root.for_each_page(&mut |page| {
+        for id in &page.outline.xrefs {
+            if !targets.contains_key(id) {
+                error!(target: "check_xrefs", "{}: unknown id [[{id}]]", page.rel_path);
+                errors += 1;
+            }
+        }
+    });
+
+    (XRefs { targets }, errors)
+}
+
+// turns every `[[id]]` into a relative link to the target, with the target's title as the text
+pub fn apply_xrefs(page: &mut NavPage, xrefs: &XRefs) {
+    let path = page.path.normalize();
+    let dir = path.parent().unwrap();
+
+    page.doc.transform(|events| {
+        md::map_xrefs(events, |id| {
+            let target = xrefs.get(id).expect("bug? unchecked xref");
+
+            let dest = if target.page == path {
+                format!("#{id}")
+            } else {
+                format!("{}#{id}", target.page.relative_from(&dir))
+            };
+
+            let link = Tag::Link(LinkType::Inline, dest.into(), "".into());
+
+            vec![
+                Event::Start(link.clone()),
+                Event::Text(target.title.clone().into()),
+                Event::End(link),
+            ]
+        })
+    });
+}